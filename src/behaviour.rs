@@ -0,0 +1,251 @@
+use std::fs::File;
+use std::io::Read;
+use std::str::FromStr;
+use find_folder;
+
+/// Outcome of running a node for one update
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Status {
+    Success,
+    Failure,
+    /// not done yet, run again next update
+    Running,
+}
+
+/// Something that carries out the actions at the leaves of a tree: a miner in the world, or a mock of it.
+pub trait Agent<A> {
+    fn run(&mut self, action: &A) -> Status;
+}
+
+/// A behaviour tree over actions of type `A`.
+///
+/// Trees hold no state of their own, they are run from the root on every update
+/// and the agent remembers what it is in the middle of.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Node<A> {
+    /// runs the children in order as long as they succeed
+    Sequence(Vec<Node<A>>),
+    /// runs the children in order until one doesn't fail
+    Selector(Vec<Node<A>>),
+    /// turns success into failure and the other way round
+    Invert(Box<Node<A>>),
+    /// succeeds once the child is done, however it went
+    Succeed(Box<Node<A>>),
+    Action(A),
+}
+
+impl<A> Node<A> {
+    pub fn run<G: Agent<A>>(&self, agent: &mut G) -> Status {
+        match *self {
+            Node::Sequence(ref children) => {
+                for child in children.iter() {
+                    let status = child.run(agent);
+                    if status != Status::Success {
+                        return status;
+                    }
+                }
+                Status::Success
+            },
+            Node::Selector(ref children) => {
+                for child in children.iter() {
+                    let status = child.run(agent);
+                    if status != Status::Failure {
+                        return status;
+                    }
+                }
+                Status::Failure
+            },
+            Node::Invert(ref child) => match child.run(agent) {
+                Status::Success => Status::Failure,
+                Status::Failure => Status::Success,
+                Status::Running => Status::Running,
+            },
+            Node::Succeed(ref child) => match child.run(agent) {
+                Status::Running => Status::Running,
+                _ => Status::Success,
+            },
+            Node::Action(ref action) => agent.run(action),
+        }
+    }
+}
+
+/// Reads a tree written as nested lists, e.g.
+///
+/// ```text
+/// ; eat when hungry, work otherwise
+/// (selector
+///   (sequence is-hungry eat)
+///   work)
+/// ```
+///
+/// Bare words are actions, parsed with `A::from_str`.
+pub fn parse<A: FromStr<Err = String>>(source: &str) -> Result<Node<A>, String> {
+    let tokens = tokenize(source);
+    let mut position = 0;
+    let node = parse_node(&tokens, &mut position)?;
+    if position < tokens.len() {
+        return Err(format!("Unexpected '{}' after the tree", tokens[position]));
+    }
+    Ok(node)
+}
+
+/// Reads a tree from a file in the assets folder.
+pub fn load<A: FromStr<Err = String>>(filename: &str) -> Result<Node<A>, String> {
+    let assets = find_folder::Search::ParentsThenKids(3, 3)
+        .for_folder("assets").map_err(|e| format!("{:?}", e))?;
+    let mut source = String::new();
+    File::open(assets.join(filename))
+        .and_then(|mut file| file.read_to_string(&mut source))
+        .map_err(|e| format!("Can't read {}: {}", filename, e))?;
+    parse(&source)
+}
+
+fn tokenize(source: &str) -> Vec<String> {
+    let mut tokens = Vec::new();
+    for line in source.lines() {
+        let line = line.split(';').next().unwrap();
+        for word in line.replace('(', " ( ").replace(')', " ) ").split_whitespace() {
+            tokens.push(word.to_string());
+        }
+    }
+    tokens
+}
+
+fn parse_node<A: FromStr<Err = String>>(tokens: &[String], position: &mut usize) -> Result<Node<A>, String> {
+    if *position >= tokens.len() {
+        return Err("Unexpected end of the tree".to_string());
+    }
+    let token = tokens[*position].clone();
+    *position += 1;
+    if token == ")" {
+        return Err("Unexpected ')'".to_string());
+    }
+    if token != "(" {
+        return A::from_str(&token).map(Node::Action);
+    }
+    let kind = match tokens.get(*position) {
+        Some(kind) => kind.clone(),
+        None => return Err("Unexpected end of the tree".to_string()),
+    };
+    *position += 1;
+    let mut children = Vec::new();
+    while tokens.get(*position).map_or(false, |token| token != ")") {
+        children.push(parse_node(tokens, position)?);
+    }
+    if *position >= tokens.len() {
+        return Err(format!("Missing ')' for '{}'", kind));
+    }
+    *position += 1;
+    match kind.as_str() {
+        "sequence" => Ok(Node::Sequence(children)),
+        "selector" => Ok(Node::Selector(children)),
+        "invert" | "succeed" if children.len() != 1 => Err(format!("'{}' takes one child", kind)),
+        "invert" => Ok(Node::Invert(Box::new(children.pop().unwrap()))),
+        "succeed" => Ok(Node::Succeed(Box::new(children.pop().unwrap()))),
+        _ => Err(format!("Unknown node '{}'", kind)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+    use super::*;
+
+    #[derive(Copy, Clone, PartialEq, Debug)]
+    enum Act {
+        Pass,
+        Fail,
+        Busy,
+    }
+
+    impl FromStr for Act {
+        type Err = String;
+
+        fn from_str(name: &str) -> Result<Act, String> {
+            match name {
+                "pass" => Ok(Act::Pass),
+                "fail" => Ok(Act::Fail),
+                "busy" => Ok(Act::Busy),
+                _ => Err(format!("Unknown action '{}'", name)),
+            }
+        }
+    }
+
+    /// Answers every action with the status it stands for and remembers what it was asked to do.
+    struct Mock {
+        ran: Vec<Act>,
+    }
+
+    impl Agent<Act> for Mock {
+        fn run(&mut self, action: &Act) -> Status {
+            self.ran.push(*action);
+            match *action {
+                Act::Pass => Status::Success,
+                Act::Fail => Status::Failure,
+                Act::Busy => Status::Running,
+            }
+        }
+    }
+
+    fn run(source: &str) -> (Status, Vec<Act>) {
+        let tree = parse::<Act>(source).unwrap();
+        let mut mock = Mock { ran: Vec::new() };
+        let status = tree.run(&mut mock);
+        (status, mock.ran)
+    }
+
+    #[test]
+    fn action() {
+        assert_eq!(run("pass"), (Status::Success, vec![Act::Pass]));
+        assert_eq!(run("fail"), (Status::Failure, vec![Act::Fail]));
+        assert_eq!(run("busy"), (Status::Running, vec![Act::Busy]));
+    }
+
+    #[test]
+    fn sequence() {
+        assert_eq!(run("(sequence pass pass)"), (Status::Success, vec![Act::Pass, Act::Pass]));
+        assert_eq!(run("(sequence pass fail pass)"), (Status::Failure, vec![Act::Pass, Act::Fail]));
+        assert_eq!(run("(sequence busy pass)"), (Status::Running, vec![Act::Busy]));
+        assert_eq!(run("(sequence)"), (Status::Success, vec![]));
+    }
+
+    #[test]
+    fn selector() {
+        assert_eq!(run("(selector fail pass fail)"), (Status::Success, vec![Act::Fail, Act::Pass]));
+        assert_eq!(run("(selector fail fail)"), (Status::Failure, vec![Act::Fail, Act::Fail]));
+        assert_eq!(run("(selector fail busy pass)"), (Status::Running, vec![Act::Fail, Act::Busy]));
+        assert_eq!(run("(selector)"), (Status::Failure, vec![]));
+    }
+
+    #[test]
+    fn invert() {
+        assert_eq!(run("(invert pass)").0, Status::Failure);
+        assert_eq!(run("(invert fail)").0, Status::Success);
+        assert_eq!(run("(invert busy)").0, Status::Running);
+    }
+
+    #[test]
+    fn succeed() {
+        assert_eq!(run("(succeed pass)").0, Status::Success);
+        assert_eq!(run("(succeed fail)").0, Status::Success);
+        assert_eq!(run("(succeed busy)").0, Status::Running);
+    }
+
+    #[test]
+    fn nested_with_comments() {
+        let source = "; the first thing that works\n(selector\n  (sequence fail pass) ; skipped\n  (invert fail))";
+        assert_eq!(run(source), (Status::Success, vec![Act::Fail, Act::Fail]));
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert_eq!(parse::<Act>("(sequence pass"), Err("Missing ')' for 'sequence'".to_string()));
+        assert_eq!(parse::<Act>("(sequence pass))"), Err("Unexpected ')' after the tree".to_string()));
+        assert_eq!(parse::<Act>(")"), Err("Unexpected ')'".to_string()));
+        assert_eq!(parse::<Act>("("), Err("Unexpected end of the tree".to_string()));
+        assert_eq!(parse::<Act>(""), Err("Unexpected end of the tree".to_string()));
+        assert_eq!(parse::<Act>("(parallel pass)"), Err("Unknown node 'parallel'".to_string()));
+        assert_eq!(parse::<Act>("(sequence dance)"), Err("Unknown action 'dance'".to_string()));
+        assert_eq!(parse::<Act>("(invert pass fail)"), Err("'invert' takes one child".to_string()));
+    }
+}
@@ -0,0 +1,58 @@
+/// Simulation time advanced per step, in seconds
+pub const STEP: f32 = 1.0 / 30.0;
+/// Steps run per frame at most, so a slow frame doesn't snowball
+const MAX_STEPS_PER_FRAME: u32 = 32;
+/// Steps carried over to the next frames at most; anything owed beyond that is dropped
+const MAX_BACKLOG: u32 = 64;
+
+/// Simulation time owed to the game, paid out in fixed steps so the simulation is the same at any frame rate.
+pub struct Clock {
+    /// Seconds not simulated yet
+    pub owed: f32,
+}
+
+impl Clock {
+    pub fn new() -> Clock {
+        Clock {
+            owed: 0.0,
+        }
+    }
+
+    /// Adds simulated seconds and returns how many steps to run this frame.
+    ///
+    /// Steps that don't fit in the frame are run in the next ones, up to a backlog.
+    pub fn advance(&mut self, seconds: f32) -> u32 {
+        self.owed = (self.owed + seconds).min((MAX_STEPS_PER_FRAME + MAX_BACKLOG) as f32 * STEP);
+        let mut steps = 0;
+        while self.owed >= STEP && steps < MAX_STEPS_PER_FRAME {
+            self.owed -= STEP;
+            steps += 1;
+        }
+        steps
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn leftovers_are_carried() {
+        let mut clock = Clock::new();
+        assert_eq!(clock.advance(STEP * 0.5), 0);
+        assert_eq!(clock.advance(STEP * 0.75), 1);
+        assert!((clock.owed - STEP * 0.25).abs() < 1e-6);
+        assert_eq!(clock.advance(STEP * 3.0), 3);
+    }
+
+    #[test]
+    fn backlog_is_bounded() {
+        let mut clock = Clock::new();
+        // a long hitch is paid out over the next frames, as far as the backlog goes
+        assert_eq!(clock.advance(STEP * 1000.5), MAX_STEPS_PER_FRAME);
+        assert_eq!(clock.advance(0.0), MAX_STEPS_PER_FRAME);
+        assert_eq!(clock.advance(0.0), MAX_BACKLOG - MAX_STEPS_PER_FRAME);
+        assert_eq!(clock.advance(0.0), 0);
+        assert!(clock.owed < STEP);
+    }
+}
@@ -16,10 +16,33 @@ mod selection;
 mod quadtree;
 mod miners;
 mod tiles;
+mod structure;
+mod pathfinding;
+mod hpa;
+mod flowfield;
+mod movement;
+mod jobs;
+mod reservations;
+mod inventory;
+mod needs;
+mod health;
+mod skills;
+mod social;
+mod behaviour;
+mod utility;
+mod identity;
+mod mood;
+mod stockpiles;
+mod population;
+mod wildlife;
+mod clock;
+mod weather;
+mod fire;
 
 use gfx::{Device, GraphicsPoolExt};
 use support::{BackbufferView, ColorFormat};
 use std::collections::HashSet;
+use std::process;
 use sdl2::event::Event;
 use sdl2::keyboard::Keycode;
 
@@ -32,6 +55,8 @@ const SPRITE_VERTICES: [Vertex; 4] = [
 ];
 
 const SPRITE_INDICES: [u16; 6] = [0, 1, 2, 1, 3, 2];
+/// Height of the line of text in the corner
+const TEXT_HEIGHT: f32 = 20.0;
 
 const SPRITE_MINER: u32 = 0;
 const SPRITE_WATER: u32 = 1;
@@ -40,8 +65,31 @@ const SPRITE_CLAY: u32 = 3;
 const SPRITE_STONE: u32 = 4;
 const SPRITE_TREE: u32 = 5;
 const SPRITE_WOOD: u32 = 6;
+const SPRITE_FLOOR: u32 = 7;
+const SPRITE_SMOOTH_FLOOR: u32 = 8;
+const SPRITE_HOLE: u32 = 9;
+const SPRITE_ROCK: u32 = 10;
+const SPRITE_PILLAR: u32 = 11;
+const SPRITE_SHRUB: u32 = 12;
+const SPRITE_FOOD: u32 = 13;
+const SPRITE_CORPSE: u32 = 14;
+const SPRITE_DEER: u32 = 15;
+const SPRITE_BIRD: u32 = 16;
+const SPRITE_WOLF: u32 = 17;
+const SPRITE_DRINK: u32 = 18;
+const SPRITE_BED: u32 = 19;
 
 const RESOURCE_WOOD: u8 = 0;
+const RESOURCE_STONE: u8 = 1;
+const RESOURCE_FOOD: u8 = 2;
+const RESOURCE_DRINK: u8 = 3;
+
+/// Grounds a fill can lay down, switched with V
+const FILL_MATERIALS: [u32; 3] = [SPRITE_FLOOR, SPRITE_CLAY, SPRITE_STONE];
+
+const CAVE_IN_DAMAGE: f32 = 50.0;
+
+const MAX_GAME_SPEED: f32 = 8.0;
 
 gfx_defines!{
     vertex Vertex {
@@ -53,6 +101,8 @@ gfx_defines!{
         translate: [f32; 2] = "a_Translate",
         tex_id: u32 = "i_tex_id",
         is_selected: f32 = "is_selected",
+        light: f32 = "i_light",
+        overlay: f32 = "i_overlay",
     }
 
     pipeline pipe {
@@ -65,16 +115,44 @@ gfx_defines!{
     }
 }
 
+const EMPTY_INSTANCE: Instance = Instance {
+    translate: [0.0, 0.0],
+    tex_id: 0,
+    is_selected: 0.0,
+    light: 0.0,
+    overlay: 0.0,
+};
+
 fn fill_instances(instances: &mut [Instance], start_idx: usize, tiles: &Vec<&tiles::Tile>) {
     for (i, tile) in tiles.iter().enumerate() {
         instances[start_idx + i] = Instance {
             translate: [tile.position.x, tile.position.y],
             tex_id: tile.tex_id,
             is_selected: match tile.is_selected { true => 0.5, false => 0.0 },
+            // undiscovered tiles are not drawn, remembered ones are drawn dark
+            light: match (tile.is_discovered && !tile.is_removed, tile.is_visible) {
+                (false, _) => 0.0,
+                (true, false) => 0.5,
+                (true, true) => 1.0,
+            },
+            overlay: 0.0,
         };
     }
  }
 
+/// What a mouse selection does once it is released
+#[derive(Copy, Clone, Debug)]
+enum Tool {
+    /// queue jobs of a kind
+    Designate(jobs::JobKind),
+    /// drop the jobs on the selected cells
+    Cancel,
+    /// give the jobs on the selected cells the current priority
+    Prioritize,
+    /// mark the selected cells as a stockpile for a resource, or for anything
+    Stockpile { accepts: Option<u8>, capacity: usize },
+}
+
 pub struct App<B: gfx::Backend> {
     running: bool,
     zoom: f32,
@@ -88,10 +166,89 @@ pub struct App<B: gfx::Backend> {
     slice_ui: gfx::Slice<B::Resources>,
     tiles: tiles::Tiles,
     miners: miners::Miners,
+    wildlife: wildlife::Wildlife,
+    structure: structure::Structure,
+    hpa: hpa::Hpa,
+    flow_fields: flowfield::FlowFields,
+    jobs: jobs::Jobs,
+    stockpiles: stockpiles::Stockpiles,
+    population: population::Population,
     instance_count: usize,
+    /// Instance data of `tiles`, refreshed only for the tiles that changed
+    tile_instances: Vec<Instance>,
     prev_buttons: HashSet<sdl2::mouse::MouseButton>,
     selection: selection::Selection,
     cur_tile: Option<usize>,
+    /// Miner whose labours the function keys toggle
+    cur_miner: Option<usize>,
+    /// Ground laid down by the fills designated next
+    fill_material: u32,
+    tool: Tool,
+    /// Priority of the jobs the tools designate or prioritize
+    priority: u8,
+    /// Whether job priorities are tinted onto the map
+    show_priorities: bool,
+    /// Simulated seconds per real second
+    game_speed: f32,
+    paused: bool,
+    clock: clock::Clock,
+    weather: weather::Weather,
+    fires: fire::Fires,
+}
+
+impl<B: gfx::Backend> App<B> {
+    /// Advances the world by some simulation time.
+    fn step(&mut self, duration: f32) {
+        self.miners.update(duration, &mut self.tiles, &mut self.jobs, &self.stockpiles, &mut self.hpa,
+                           &mut self.flow_fields);
+        self.population.update(duration, &mut self.miners, &mut self.tiles, &self.stockpiles);
+        let miner_positions = self.miners.miners.iter().map(|miner| miner.tile.position).collect::<Vec<_>>();
+        let bitten = self.wildlife.update(duration, &self.tiles, &self.hpa, &miner_positions)
+            .into_iter()
+            .map(|i| self.miners.miners[i].id)
+            .collect::<Vec<_>>();
+        for id in bitten {
+            self.miners.hurt(id, health::Cause::Combat, wildlife::BITE_DAMAGE);
+        }
+        let caved_in = self.structure.update(duration, &mut self.tiles);
+        self.miners.hurt_at(&caved_in, health::Cause::CaveIn, CAVE_IN_DAMAGE, &self.tiles);
+        let raining = self.weather.update(duration);
+        if raining {
+            self.miners.rain_on(&self.tiles);
+        }
+        let scorched = self.fires.update(duration, &mut self.tiles, raining);
+        self.miners.hurt_at(&scorched, health::Cause::Fire, fire::BURN_DAMAGE, &self.tiles);
+        self.tiles.update_visibility(&self.miners.get_viewers());
+        self.handle_tile_events();
+    }
+
+    /// Reacts to the changes made to `tiles` since the last frame.
+    fn handle_tile_events(&mut self) {
+        let events = self.tiles.take_events();
+        for event in events.iter() {
+            match *event {
+                tiles::TileEvent::Changed(id) |
+                tiles::TileEvent::Spawned(id) |
+                tiles::TileEvent::Removed(id) => {
+                    while self.tile_instances.len() <= id {
+                        self.tile_instances.push(EMPTY_INSTANCE);
+                    }
+                    fill_instances(&mut self.tile_instances, id, &vec![&self.tiles.tiles[id]]);
+                },
+            }
+        }
+        // carried items move without events
+        for miner in self.miners.miners.iter() {
+            for &item in miner.inventory.items.iter() {
+                fill_instances(&mut self.tile_instances, item, &vec![&self.tiles.tiles[item]]);
+            }
+        }
+        self.instance_count = self.tile_instances.len() + self.miners.miners.len() + self.wildlife.animals.len();
+        self.structure.handle_events(&self.tiles, &events);
+        self.hpa.handle_events(&self.tiles, &events);
+        self.flow_fields.handle_events(&self.tiles, &events);
+        self.jobs.handle_events(&self.tiles, &events, &self.stockpiles);
+    }
 }
 
 impl<B: gfx::Backend> support::Application<B> for App<B> {
@@ -112,11 +269,30 @@ impl<B: gfx::Backend> support::Application<B> for App<B> {
         };
 
         let mut tiles = tiles::Tiles::new_layer_from_heightmap("heightmap_64.png", 2);
-        let mut miners = miners::Miners::new(10, &tiles);
+        let mut miners = match miners::Miners::new(10, &tiles) {
+            Ok(miners) => miners,
+            Err(error) => {
+                println!("{}", error);
+                process::exit(1);
+            },
+        };
+        for miner in miners.miners.iter() {
+            let cell = tiles.cell_at(miner.tile.position).unwrap();
+            population::bring_provisions(&mut tiles, cell, 1);
+        }
+        tiles.update_visibility(&miners.get_viewers());
+        let wildlife = wildlife::Wildlife::new(&tiles);
         let miners_count: usize = miners.miners.len();
+        let animals_count: usize = wildlife.animals.len();
         let sprites_count: usize = tiles.tiles.len();
-        let instance_count = sprites_count + miners_count;
+        let instance_count = sprites_count + miners_count + animals_count;
         println!("Number of sprites: {}", instance_count);
+        let mut tile_instances = vec![EMPTY_INSTANCE; sprites_count];
+        fill_instances(&mut tile_instances, 0, &tiles.get_tiles());
+        tiles.take_events();
+        let structure = structure::Structure::new(&tiles);
+        let hpa = hpa::Hpa::new(&tiles);
+        let flow_fields = flowfield::FlowFields::new(&tiles);
 
         let zoom = 1.0;
         let (viewport_w, viewport_h) = (800.0, 600.0);
@@ -145,8 +321,16 @@ impl<B: gfx::Backend> support::Application<B> for App<B> {
             viewport_w: viewport_w,
             viewport_h: viewport_h,
             miners: miners,
+            wildlife: wildlife,
             tiles: tiles,
+            structure: structure,
+            hpa: hpa,
+            flow_fields: flow_fields,
+            jobs: jobs::Jobs::new(),
+            stockpiles: stockpiles::Stockpiles::new(),
+            population: population::Population::new(),
             instance_count: instance_count,
+            tile_instances: tile_instances,
             slice: slice,
             slice_ui: slice_ui,
             data: pipe::Data {
@@ -179,6 +363,16 @@ impl<B: gfx::Backend> support::Application<B> for App<B> {
             prev_buttons: HashSet::new(),
             selection: selection::Selection::new(),
             cur_tile: None,
+            cur_miner: None,
+            fill_material: SPRITE_FLOOR,
+            tool: Tool::Designate(jobs::JobKind::Chop),
+            priority: jobs::DEFAULT_PRIORITY,
+            show_priorities: false,
+            game_speed: 1.0,
+            paused: false,
+            clock: clock::Clock::new(),
+            weather: weather::Weather::new(),
+            fires: fire::Fires::new(),
         }
     }
 
@@ -193,8 +387,21 @@ impl<B: gfx::Backend> support::Application<B> for App<B> {
         let upload = device.create_upload_buffer(self.instance_count).unwrap();
         {
             let mut writer = device.write_mapping(&upload).unwrap();
-            fill_instances(&mut writer, 0, &self.tiles.get_tiles());
-            fill_instances(&mut writer, self.tiles.tiles.len(), &self.miners.get_tiles());
+            writer[..self.tile_instances.len()].copy_from_slice(&self.tile_instances);
+            fill_instances(&mut writer, self.tile_instances.len(), &self.miners.get_tiles());
+            fill_instances(&mut writer, self.tile_instances.len() + self.miners.miners.len(),
+                           &self.wildlife.get_tiles());
+            if self.show_priorities {
+                // tint the ground under every job, from cold for low to hot for high priorities
+                for job in self.jobs.queue.iter() {
+                    let ground = self.tiles.ground[self.tiles.cell_index(job.cell)];
+                    writer[ground].overlay = job.priority as f32 / jobs::MAX_PRIORITY as f32;
+                }
+            }
+            // burning trees glow
+            for fire in self.fires.burning.iter() {
+                writer[fire.tree].overlay = 1.0;
+            }
         };
 
         self.slice.instances = Some((self.instance_count as u32, 0));
@@ -215,12 +422,28 @@ impl<B: gfx::Backend> support::Application<B> for App<B> {
         encoder.clear(&self.data.out, [0.1, 0.2, 0.3, 1.0]);
         encoder.draw(&self.slice, &self.pso, &self.data);
 
-        // update UI pipeline data
+        // update UI pipeline data, keeping the text at its own aspect in the top left corner
+        let (text_w, text_h) = text_surface.size();
+        let half_h = TEXT_HEIGHT / 2.0;
+        let half_w = half_h * text_w as f32 / text_h.max(1) as f32;
+        let text_vertices = [
+            Vertex { position: [-half_w,  half_h] },
+            Vertex { position: [ half_w,  half_h] },
+            Vertex { position: [-half_w, -half_h] },
+            Vertex { position: [ half_w, -half_h] },
+        ];
+        let (text_quad, slice_ui) = device.create_vertex_buffer_with_slice(&text_vertices, &SPRITE_INDICES[..]);
+        self.data_ui.vertex = text_quad;
+        self.slice_ui = slice_ui;
         let text_upload = device.create_upload_buffer(1).unwrap();
         {
             let mut writer = device.write_mapping(&text_upload).unwrap();
-            fill_instances(&mut writer, 0, &vec![
-                &tiles::Tile::new(cgmath::Vector2::new(160.0, 221.0), 0, None)]);
+            let position = cgmath::Vector2::new(- self.viewport_w / 2.0 + half_w + 8.0,
+                                                - self.viewport_h / 2.0 + half_h + 8.0);
+            let mut text = tiles::Tile::new(position, 0, None);
+            text.is_discovered = true;
+            text.is_visible = true;
+            fill_instances(&mut writer, 0, &vec![&text]);
         };
         self.data_ui.instance = device
             .create_buffer(1,
@@ -262,22 +485,66 @@ impl<B: gfx::Backend> support::Application<B> for App<B> {
             println!("Mouse coord: {:?}, {:?}", x, y);
             let picked_tile_id = self.tiles.tree.find(&cgmath::Vector2::new(x * self.zoom, y * self.zoom));
             if self.cur_tile.is_some() {
-                self.tiles.tiles[self.cur_tile.unwrap()].is_selected = false;
+                self.tiles.set_selected(self.cur_tile.unwrap(), false);
             }
             if picked_tile_id.is_some() {
                 let sel_id = picked_tile_id.unwrap();
-                self.tiles.tiles[sel_id].is_selected = true;
+                self.tiles.set_selected(sel_id, true);
                 println!("Sprite coords: {:?}", self.tiles.tiles[sel_id].position);
                 self.cur_tile = Some(sel_id);
             }
             println!("Clicked at tile: {:?}", picked_tile_id);
+            let picked_miner = self.miners.miner_at(cgmath::Vector2::new(x * self.zoom, y * self.zoom),
+                                                    self.tiles.step_x);
+            if picked_miner.is_some() {
+                let miner = self.miners.get(picked_miner.unwrap()).unwrap();
+                println!("Picked {} ({}, {:?}, {:?}), does {:?}", miner.identity.name, miner.identity.age,
+                         miner.identity.attributes, miner.identity.traits, miner.labours);
+                let thoughts = miner.mood.recent(5).iter()
+                    .map(|thought| format!("{} ({:+})", thought.kind.describe(), thought.effect))
+                    .collect::<Vec<_>>();
+                println!("Mood {:.0}, lately {}", miner.mood.score(), thoughts.join(", "));
+                println!("Health {:.0}/{:.0}{}, wounds {:?}", miner.health.current(), miner.health.max,
+                         if miner.health.is_bleeding() { ", bleeding" } else { "" }, miner.health.wounds());
+                let relations = self.miners.relationships.of(miner.id).iter()
+                    .filter_map(|&(other, relationship)| self.miners.get(other)
+                        .map(|other| format!("{} ({:?} {:.0})", other.identity.name, relationship.bond, relationship.opinion)))
+                    .collect::<Vec<_>>();
+                println!("Knows {}", relations.join(", "));
+                self.cur_miner = picked_miner;
+            }
         }
         self.selection.update(x, y, &new_buttons, &old_buttons, &buttons);
-        self.miners.update(tick as f32, &mut self.tiles);
+        if !self.paused {
+            let steps = self.clock.advance(tick * self.game_speed);
+            for _ in 0..steps {
+                self.step(clock::STEP);
+            }
+        }
 
         if self.selection.pressed {
             self.tiles.update_selected(&self.selection);
+        } else if old_buttons.contains(&sdl2::mouse::MouseButton::Left) {
+            // the selection was just released, apply the tool to it
+            let cells = self.tiles.selected_cells();
+            match self.tool {
+                Tool::Designate(jobs::JobKind::Fill) => {
+                    self.jobs.designate_fill(&self.tiles, &cells, self.fill_material, self.priority);
+                },
+                Tool::Designate(kind) => { self.jobs.designate(&self.tiles, &cells, kind, self.priority); },
+                Tool::Cancel => { self.jobs.cancel_at(&cells); },
+                Tool::Prioritize => { self.jobs.set_priority(&cells, self.priority); },
+                Tool::Stockpile { accepts, capacity } => {
+                    let accepts = accepts.into_iter().collect::<Vec<_>>();
+                    self.stockpiles.designate(&self.tiles, &cells, accepts, capacity);
+                    // items lying there may not be welcome anymore
+                    let items = cells.iter().flat_map(|&cell| self.tiles.items_at(cell)).collect::<Vec<_>>();
+                    self.jobs.queue_hauls(&self.tiles, items, &self.stockpiles);
+                },
+            }
+            self.tiles.update_selected(&self.selection);
         }
+        self.handle_tile_events();
 
         // handle events
         for event in events.poll_iter() {
@@ -292,6 +559,135 @@ impl<B: gfx::Backend> support::Application<B> for App<B> {
                 Event::KeyDown { keycode: Some(Keycode::Minus), .. } => {
                     self.zoom += 0.5;
                 },
+                Event::KeyDown { keycode: Some(Keycode::V), .. } => {
+                    let next = FILL_MATERIALS.iter().position(|&m| m == self.fill_material).map_or(0, |i| i + 1);
+                    self.fill_material = FILL_MATERIALS[next % FILL_MATERIALS.len()];
+                    println!("Filling with {:?}", self.fill_material);
+                },
+                Event::KeyDown { keycode: Some(Keycode::Space), .. } => {
+                    self.paused = !self.paused;
+                    println!("Paused: {}", self.paused);
+                },
+                Event::KeyDown { keycode: Some(Keycode::Period), .. } => {
+                    self.game_speed = (self.game_speed * 2.0).min(MAX_GAME_SPEED);
+                    println!("Game speed: {}", self.game_speed);
+                },
+                Event::KeyDown { keycode: Some(Keycode::Comma), .. } => {
+                    self.game_speed = (self.game_speed / 2.0).max(0.25);
+                    println!("Game speed: {}", self.game_speed);
+                },
+                Event::KeyDown { keycode: Some(Keycode::C), .. } => {
+                    self.tool = Tool::Designate(jobs::JobKind::Chop);
+                },
+                Event::KeyDown { keycode: Some(Keycode::M), .. } => {
+                    self.tool = Tool::Designate(jobs::JobKind::Mine);
+                },
+                Event::KeyDown { keycode: Some(Keycode::G), .. } => {
+                    self.tool = Tool::Designate(jobs::JobKind::Gather);
+                },
+                Event::KeyDown { keycode: Some(Keycode::B), .. } => {
+                    self.tool = Tool::Designate(jobs::JobKind::Build);
+                },
+                Event::KeyDown { keycode: Some(Keycode::H), .. } => {
+                    self.tool = Tool::Designate(jobs::JobKind::Channel);
+                },
+                Event::KeyDown { keycode: Some(Keycode::N), .. } => {
+                    self.tool = Tool::Designate(jobs::JobKind::Smooth);
+                },
+                Event::KeyDown { keycode: Some(Keycode::F), .. } => {
+                    self.tool = Tool::Designate(jobs::JobKind::Fill);
+                },
+                Event::KeyDown { keycode: Some(Keycode::E), .. } => {
+                    self.tool = Tool::Designate(jobs::JobKind::Furnish);
+                },
+                Event::KeyDown { keycode: Some(Keycode::X), .. } => {
+                    self.tool = Tool::Cancel;
+                },
+                Event::KeyDown { keycode: Some(Keycode::P), .. } => {
+                    self.tool = Tool::Prioritize;
+                },
+                Event::KeyDown { keycode: Some(Keycode::O), .. } => {
+                    self.show_priorities = !self.show_priorities;
+                },
+                Event::KeyDown { keycode: Some(key @ Keycode::Num1), .. } |
+                Event::KeyDown { keycode: Some(key @ Keycode::Num2), .. } |
+                Event::KeyDown { keycode: Some(key @ Keycode::Num3), .. } |
+                Event::KeyDown { keycode: Some(key @ Keycode::Num4), .. } |
+                Event::KeyDown { keycode: Some(key @ Keycode::Num5), .. } |
+                Event::KeyDown { keycode: Some(key @ Keycode::Num6), .. } |
+                Event::KeyDown { keycode: Some(key @ Keycode::Num7), .. } => {
+                    self.priority = match key {
+                        Keycode::Num1 => 1,
+                        Keycode::Num2 => 2,
+                        Keycode::Num3 => 3,
+                        Keycode::Num4 => 4,
+                        Keycode::Num5 => 5,
+                        Keycode::Num6 => 6,
+                        _ => 7,
+                    };
+                    println!("Priority: {}", self.priority);
+                },
+                Event::KeyDown { keycode: Some(Keycode::S), .. } => {
+                    // pressing again cycles through the filters
+                    self.tool = match self.tool {
+                        Tool::Stockpile { accepts: None, capacity } =>
+                            Tool::Stockpile { accepts: Some(RESOURCE_WOOD), capacity: capacity },
+                        Tool::Stockpile { accepts: Some(RESOURCE_WOOD), capacity } =>
+                            Tool::Stockpile { accepts: Some(RESOURCE_STONE), capacity: capacity },
+                        Tool::Stockpile { accepts: Some(RESOURCE_STONE), capacity } =>
+                            Tool::Stockpile { accepts: Some(RESOURCE_FOOD), capacity: capacity },
+                        Tool::Stockpile { accepts: Some(RESOURCE_FOOD), capacity } =>
+                            Tool::Stockpile { accepts: Some(RESOURCE_DRINK), capacity: capacity },
+                        Tool::Stockpile { capacity, .. } => Tool::Stockpile { accepts: None, capacity: capacity },
+                        _ => Tool::Stockpile { accepts: None, capacity: 1 },
+                    };
+                    println!("Tool: {:?}", self.tool);
+                },
+                Event::KeyDown { keycode: Some(Keycode::LeftBracket), .. } => {
+                    if let Tool::Stockpile { accepts, capacity } = self.tool {
+                        self.tool = Tool::Stockpile { accepts: accepts, capacity: capacity.max(2) - 1 };
+                        println!("Tool: {:?}", self.tool);
+                    }
+                },
+                Event::KeyDown { keycode: Some(Keycode::RightBracket), .. } => {
+                    if let Tool::Stockpile { accepts, capacity } = self.tool {
+                        self.tool = Tool::Stockpile { accepts: accepts, capacity: capacity + 1 };
+                        println!("Tool: {:?}", self.tool);
+                    }
+                },
+                Event::KeyDown { keycode: Some(key @ Keycode::F1), .. } |
+                Event::KeyDown { keycode: Some(key @ Keycode::F2), .. } |
+                Event::KeyDown { keycode: Some(key @ Keycode::F3), .. } |
+                Event::KeyDown { keycode: Some(key @ Keycode::F4), .. } |
+                Event::KeyDown { keycode: Some(key @ Keycode::F5), .. } |
+                Event::KeyDown { keycode: Some(key @ Keycode::F6), .. } |
+                Event::KeyDown { keycode: Some(key @ Keycode::F7), .. } |
+                Event::KeyDown { keycode: Some(key @ Keycode::F8), .. } |
+                Event::KeyDown { keycode: Some(key @ Keycode::F9), .. } => {
+                    // toggle a labour of the picked miner, in the order of `JOB_KINDS`
+                    let kind = jobs::JOB_KINDS[match key {
+                        Keycode::F1 => 0,
+                        Keycode::F2 => 1,
+                        Keycode::F3 => 2,
+                        Keycode::F4 => 3,
+                        Keycode::F5 => 4,
+                        Keycode::F6 => 5,
+                        Keycode::F7 => 6,
+                        Keycode::F8 => 7,
+                        _ => 8,
+                    }];
+                    if self.cur_miner.is_some() {
+                        self.miners.toggle_labour(self.cur_miner.unwrap(), kind, &mut self.jobs, &mut self.tiles);
+                    }
+                },
+                Event::KeyDown { keycode: Some(Keycode::R), .. } => {
+                    // rally everyone at the cell under the cursor
+                    let cell = self.tiles.cell_at(cgmath::Vector2::new(x * self.zoom, y * self.zoom));
+                    if cell.is_some() {
+                        println!("Meeting point at {:?}", cell.unwrap());
+                        self.miners.gather_at(cell.unwrap(), &mut self.jobs, &mut self.tiles);
+                    }
+                },
                 _ => {}
             }
         }
@@ -300,6 +696,15 @@ impl<B: gfx::Backend> support::Application<B> for App<B> {
     fn is_running(&self) -> bool {
         self.running
     }
+
+    /// The labours of the picked miner.
+    fn status(&self) -> String {
+        match self.cur_miner.and_then(|id| self.miners.get(id)) {
+            Some(miner) if miner.labours.is_empty() => format!("{} does nothing", miner.identity.name),
+            Some(miner) => format!("{} does {:?}", miner.identity.name, miner.labours),
+            None => String::new(),
+        }
+    }
 }
 
 pub fn main() {
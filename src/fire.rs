@@ -0,0 +1,133 @@
+use rand;
+use rand::Rng;
+use tiles::Tiles;
+
+/// Chance of lightning striking somewhere on the map every second of a shower
+const LIGHTNING_RATE: f32 = 0.01;
+/// Seconds a tree burns before nothing is left of it
+const BURN_TIME: f32 = 20.0;
+/// Chance every second of a burning tree throwing sparks at a cell around it
+const SPREAD_RATE: f32 = 0.1;
+/// Damage taken by a miner in or next to a tree going up in flames
+pub const BURN_DAMAGE: f32 = 15.0;
+
+/// A tree on fire.
+pub struct Fire {
+    pub tree: usize,
+    pub cell: (usize, usize),
+    /// Seconds until it burns down
+    pub time_left: f32,
+}
+
+/// Trees set alight by lightning and the fires spreading from them.
+pub struct Fires {
+    pub burning: Vec<Fire>,
+}
+
+impl Fires {
+    pub fn new() -> Fires {
+        Fires {
+            burning: Vec::new(),
+        }
+    }
+
+    /// Sets the tree on a cell alight, unless there is none or it burns already.
+    pub fn ignite(&mut self, tiles: &Tiles, cell: (usize, usize)) -> bool {
+        let tree = tiles.items_at(cell).into_iter()
+            .find(|&id| tiles.tiles[id].tex_id == ::SPRITE_TREE && !self.burning.iter().any(|fire| fire.tree == id));
+        match tree {
+            Some(tree) => {
+                println!("The tree at {:?} caught fire", cell);
+                self.burning.push(Fire {
+                    tree: tree,
+                    cell: cell,
+                    time_left: BURN_TIME,
+                });
+                true
+            },
+            None => false,
+        }
+    }
+
+    /// Passes time: lightning strikes while it rains, fires spread to the trees around and burn them down.
+    ///
+    /// Returns the cells scorched by trees catching fire, their own and the ones around.
+    pub fn update(&mut self, duration: f32, tiles: &mut Tiles, raining: bool) -> Vec<(usize, usize)> {
+        let mut rng = rand::thread_rng();
+        // a tree that was cut down doesn't burn any more
+        self.burning.retain(|fire| tiles.tiles[fire.tree].tex_id == ::SPRITE_TREE && !tiles.tiles[fire.tree].is_removed);
+        let mut sparks = Vec::new();
+        if raining && rng.gen::<f32>() < LIGHTNING_RATE * duration {
+            let cell = (rng.gen_range(0, tiles.width), rng.gen_range(0, tiles.height));
+            if !tiles.roofed[tiles.cell_index(cell)] {
+                sparks.push(cell);
+            }
+        }
+        for fire in self.burning.iter_mut() {
+            fire.time_left -= duration;
+            if rng.gen::<f32>() < SPREAD_RATE * duration {
+                sparks.extend(around(tiles, fire.cell).into_iter().filter(|_| rng.gen::<f32>() < 0.25));
+            }
+        }
+        let mut scorched = Vec::new();
+        for cell in sparks {
+            if self.ignite(tiles, cell) {
+                scorched.extend(around(tiles, cell));
+                scorched.push(cell);
+            }
+        }
+        for fire in self.burning.iter().filter(|fire| fire.time_left <= 0.0) {
+            println!("The tree at {:?} burnt down", fire.cell);
+            tiles.remove(Some(fire.tree));
+        }
+        self.burning.retain(|fire| fire.time_left > 0.0);
+        scorched
+    }
+}
+
+/// The cells next to a cell, corners included.
+fn around(tiles: &Tiles, (x, y): (usize, usize)) -> Vec<(usize, usize)> {
+    let mut cells = Vec::new();
+    for nx in x.saturating_sub(1)..(x + 2).min(tiles.width) {
+        for ny in y.saturating_sub(1)..(y + 2).min(tiles.height) {
+            if (nx, ny) != (x, y) {
+                cells.push((nx, ny));
+            }
+        }
+    }
+    cells
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tiles::Tile;
+
+    #[test]
+    fn trees_burn_down() {
+        let mut tiles = Tiles::from_rows(&[
+            "....",
+            "....",
+            "....",
+            "....",
+        ]);
+        let position = tiles.cell_position((0, 0));
+        let tree = tiles.spawn(Tile::new(position, ::SPRITE_TREE, None));
+        let position = tiles.cell_position((3, 3));
+        let chopped = tiles.spawn(Tile::new(position, ::SPRITE_TREE, None));
+        let mut fires = Fires::new();
+        assert!(!fires.ignite(&tiles, (1, 1)));
+        assert!(fires.ignite(&tiles, (0, 0)));
+        assert!(!fires.ignite(&tiles, (0, 0)));
+        assert!(fires.ignite(&tiles, (3, 3)));
+        assert_eq!(around(&tiles, (0, 0)), vec![(0, 1), (1, 0), (1, 1)]);
+        // cutting a tree down puts it out
+        tiles.replace(Some(chopped), ::SPRITE_WOOD, true);
+        fires.update(1.0, &mut tiles, false);
+        assert_eq!(fires.burning.len(), 1);
+        fires.update(BURN_TIME, &mut tiles, false);
+        assert!(fires.burning.is_empty());
+        assert!(tiles.tiles[tree].is_removed);
+        assert!(!tiles.tiles[chopped].is_removed);
+    }
+}
@@ -0,0 +1,203 @@
+use std::collections::HashMap;
+use pathfinding;
+use tiles::{Tiles, TileEvent};
+
+/// Next step towards one target from every cell of the map.
+pub struct FlowField {
+    pub target: (usize, usize),
+    search: pathfinding::Search,
+}
+
+impl FlowField {
+    /// Searches the whole map backwards from the target, so the cell a cell was reached from
+    /// is the next step from it towards the target.
+    ///
+    /// Returns `None` if the target can't be stood on.
+    pub fn new(tiles: &Tiles, target: (usize, usize)) -> Option<FlowField> {
+        if pathfinding::cell_cost(tiles, target.0 as isize, target.1 as isize).is_none() {
+            return None;
+        }
+        Some(FlowField {
+            target: target,
+            search: pathfinding::search_back(tiles, target, pathfinding::map_bounds(tiles)),
+        })
+    }
+
+    /// Where to go from a cell, `None` at the target or if it can't be reached.
+    pub fn next_step(&self, from: (usize, usize)) -> Option<(usize, usize)> {
+        self.search.previous(from)
+    }
+
+    /// Cost of walking from a cell to the target.
+    pub fn cost(&self, from: (usize, usize)) -> Option<f32> {
+        self.search.cost(from)
+    }
+
+    /// Whether the search reached a cell or one of its neighbours, i.e. whether a change to the cell
+    /// can change the field.
+    pub fn covers(&self, cell: (usize, usize)) -> bool {
+        for dx in -1..2 {
+            for dy in -1..2 {
+                let (x, y) = (cell.0 as isize + dx, cell.1 as isize + dy);
+                if x >= 0 && y >= 0 && self.search.cost((x as usize, y as usize)).is_some() {
+                    return true;
+                }
+            }
+        }
+        false
+    }
+}
+
+/// Flow fields built so far, one per target, dropped when the map changes where they lead through.
+pub struct FlowFields {
+    fields: HashMap<(usize, usize), FlowField>,
+    /// Last known cost of every cell, to skip changes that don't affect walking
+    costs: Vec<Option<f32>>,
+}
+
+impl FlowFields {
+    pub fn new(tiles: &Tiles) -> FlowFields {
+        FlowFields {
+            fields: HashMap::new(),
+            costs: tiles.ground.iter().map(|&id| pathfinding::tile_cost(tiles.tiles[id].tex_id)).collect(),
+        }
+    }
+
+    /// The field to a target, built on first use. `None` if the target can't be stood on.
+    pub fn get(&mut self, tiles: &Tiles, target: (usize, usize)) -> Option<&FlowField> {
+        if !self.fields.contains_key(&target) {
+            let field = match FlowField::new(tiles, target) {
+                Some(field) => field,
+                None => return None,
+            };
+            println!("Building a flow field to {:?}", target);
+            self.fields.insert(target, field);
+        }
+        self.fields.get(&target)
+    }
+
+    pub fn next_step(&mut self, tiles: &Tiles, from: (usize, usize), target: (usize, usize)) -> Option<(usize, usize)> {
+        self.get(tiles, target).and_then(|field| field.next_step(from))
+    }
+
+    /// Drops the fields covering a cell that got cheaper, more expensive, blocked or opened.
+    pub fn handle_events(&mut self, tiles: &Tiles, events: &[TileEvent]) {
+        let mut changed = Vec::new();
+        for event in events.iter() {
+            let id = match *event {
+                TileEvent::Changed(id) => id,
+                _ => continue,
+            };
+            let cell = match tiles.cell_at(tiles.tiles[id].position) {
+                Some(cell) => cell,
+                None => continue,
+            };
+            let cell_idx = tiles.cell_index(cell);
+            if tiles.ground[cell_idx] != id {
+                continue;
+            }
+            let cost = pathfinding::tile_cost(tiles.tiles[id].tex_id);
+            if self.costs[cell_idx] != cost {
+                self.costs[cell_idx] = cost;
+                changed.push(cell);
+            }
+        }
+        if changed.is_empty() {
+            return;
+        }
+        let count = self.fields.len();
+        self.fields.retain(|_, field| !changed.iter().any(|&cell| field.covers(cell)));
+        if self.fields.len() < count {
+            println!("Map changed, dropping {} flow fields", count - self.fields.len());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn leads_to_the_target() {
+        let tiles = Tiles::from_rows(&[
+            "...#....",
+            ".,.#.,,.",
+            ".,...,..",
+        ]);
+        let target = (7, 0);
+        let field = FlowField::new(&tiles, target).unwrap();
+        assert_eq!(field.next_step(target), None);
+        for x in 0..tiles.width {
+            for y in 0..tiles.height {
+                let from = (x, y);
+                let cost = match field.cost(from) {
+                    Some(cost) => cost,
+                    None => {
+                        assert_eq!(tiles.ground_at(from).tex_id, ::SPRITE_STONE);
+                        continue;
+                    },
+                };
+                let mut route = Vec::new();
+                let mut cell = from;
+                while let Some(next) = field.next_step(cell) {
+                    route.push(next);
+                    cell = next;
+                }
+                assert_eq!(cell, target);
+                // as cheap as walking the best route there
+                let best = pathfinding::find_path(&tiles, from, target).unwrap();
+                assert!((pathfinding::route_cost(&tiles, from, &route) - cost).abs() < 0.001);
+                assert!((pathfinding::route_cost(&tiles, from, &best) - cost).abs() < 0.001);
+            }
+        }
+    }
+
+    #[test]
+    fn unwalkable_target() {
+        let tiles = Tiles::from_rows(&[
+            "..#",
+            "..~",
+        ]);
+        assert!(FlowField::new(&tiles, (2, 0)).is_none());
+        let mut fields = FlowFields::new(&tiles);
+        assert!(fields.get(&tiles, (2, 1)).is_none());
+        assert_eq!(fields.next_step(&tiles, (0, 0), (2, 1)), None);
+        assert_eq!(fields.next_step(&tiles, (0, 0), (1, 1)), Some((1, 1)));
+    }
+
+    #[test]
+    fn dropped_when_the_map_changes() {
+        let mut tiles = Tiles::from_rows(&[
+            "....",
+            "....",
+        ]);
+        let mut fields = FlowFields::new(&tiles);
+        assert_eq!(fields.next_step(&tiles, (0, 0), (3, 0)), Some((1, 0)));
+        tiles.fill((1, 0), ::SPRITE_STONE);
+        tiles.fill((1, 1), ::SPRITE_STONE);
+        let events = tiles.take_events();
+        fields.handle_events(&tiles, &events);
+        assert_eq!(fields.next_step(&tiles, (0, 0), (3, 0)), None);
+    }
+
+    #[test]
+    fn kept_when_the_change_is_out_of_reach() {
+        let mut tiles = Tiles::from_rows(&[
+            "..#...",
+            "..#...",
+        ]);
+        let mut fields = FlowFields::new(&tiles);
+        fields.get(&tiles, (0, 0));
+        fields.get(&tiles, (5, 0));
+        tiles.fill((4, 1), ::SPRITE_STONE);
+        let events = tiles.take_events();
+        fields.handle_events(&tiles, &events);
+        assert!(fields.fields.contains_key(&(0, 0)));
+        assert!(!fields.fields.contains_key(&(5, 0)));
+        // opening the wall next to the reached cells changes both sides
+        tiles.dig((2, 0));
+        let events = tiles.take_events();
+        fields.handle_events(&tiles, &events);
+        assert!(fields.fields.is_empty());
+    }
+}
@@ -0,0 +1,194 @@
+/// Share of the bleeding that stops every second
+const CLOTTING: f32 = 0.05;
+/// Bleeding, in health per second, below which a wound counts as closed
+const CLOSED: f32 = 0.01;
+/// Health regained per second on every closed wound, more when resting
+const HEAL_RATE: f32 = 0.1;
+const REST_HEAL_FACTOR: f32 = 3.0;
+/// Burns heal this much slower than other wounds
+const BURN_HEAL_FACTOR: f32 = 0.5;
+
+/// What hurt someone
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Cause {
+    Fall,
+    CaveIn,
+    /// caught by a tree going up in flames
+    Fire,
+    /// bitten by a predator
+    Combat,
+    /// starving, dying of thirst or exhaustion
+    Deprivation,
+}
+
+pub const CAUSES: [Cause; 5] = [Cause::Fall, Cause::CaveIn, Cause::Fire, Cause::Combat, Cause::Deprivation];
+
+impl Cause {
+    /// Health bled per second for every point of damage when the wound is fresh.
+    fn bleeding(&self) -> f32 {
+        match *self {
+            Cause::Fall => 0.01,
+            Cause::CaveIn => 0.02,
+            Cause::Combat => 0.05,
+            // burns close right away
+            Cause::Fire | Cause::Deprivation => 0.0,
+        }
+    }
+}
+
+/// A wound and how much health it took so far.
+#[derive(Copy, Clone, Debug)]
+pub struct Injury {
+    pub cause: Cause,
+    pub damage: f32,
+    /// Health lost to it per second until it closes
+    pub bleeding: f32,
+}
+
+/// How healthy a miner is: full health less what its injuries took.
+pub struct Health {
+    pub max: f32,
+    pub injuries: Vec<Injury>,
+}
+
+impl Health {
+    pub fn new(max: f32) -> Health {
+        Health {
+            max: max,
+            injuries: Vec::new(),
+        }
+    }
+
+    pub fn current(&self) -> f32 {
+        self.max - self.injuries.iter().map(|injury| injury.damage).sum::<f32>()
+    }
+
+    pub fn is_dead(&self) -> bool {
+        self.current() <= 0.0
+    }
+
+    pub fn is_bleeding(&self) -> bool {
+        self.injuries.iter().any(|injury| injury.bleeding > 0.0)
+    }
+
+    /// Adds a wound. Deprivation adds up in a single one as the body wears down.
+    pub fn hurt(&mut self, cause: Cause, damage: f32) {
+        if damage <= 0.0 {
+            return;
+        }
+        if cause == Cause::Deprivation {
+            if let Some(injury) = self.injuries.iter_mut().find(|injury| injury.cause == cause) {
+                injury.damage += damage;
+                return;
+            }
+        }
+        self.injuries.push(Injury {
+            cause: cause,
+            damage: damage,
+            bleeding: damage * cause.bleeding(),
+        });
+    }
+
+    /// Open wounds bleed and slowly close, closed ones heal. Burns heal slowly,
+    /// and the toll of deprivation doesn't heal at all while the miner is still going without.
+    pub fn update(&mut self, duration: f32, resting: bool, deprived: bool) {
+        let heal = HEAL_RATE * duration * if resting { REST_HEAL_FACTOR } else { 1.0 };
+        for injury in self.injuries.iter_mut() {
+            if injury.cause == Cause::Deprivation && deprived {
+                continue;
+            }
+            if injury.bleeding > 0.0 {
+                injury.damage += injury.bleeding * duration;
+                injury.bleeding *= (1.0 - CLOTTING * duration).max(0.0);
+                if injury.bleeding < CLOSED {
+                    injury.bleeding = 0.0;
+                }
+            } else if injury.cause == Cause::Fire {
+                injury.damage -= heal * BURN_HEAL_FACTOR;
+            } else {
+                injury.damage -= heal;
+            }
+        }
+        self.injuries.retain(|injury| injury.damage > 0.0);
+    }
+
+    /// What did the most harm so far.
+    pub fn worst(&self) -> Option<Cause> {
+        self.wounds().into_iter()
+            .fold(None, |worst: Option<(Cause, f32)>, (cause, damage)| match worst {
+                Some((_, most)) if most >= damage => worst,
+                _ => Some((cause, damage)),
+            })
+            .map(|(cause, _)| cause)
+    }
+
+    /// Damage taken so far by cause, leaving out the ones with none.
+    pub fn wounds(&self) -> Vec<(Cause, f32)> {
+        CAUSES.iter()
+            .map(|&cause| (cause, self.injuries.iter().filter(|i| i.cause == cause).map(|i| i.damage).sum::<f32>()))
+            .filter(|&(_, damage)| damage > 0.0)
+            .collect()
+    }
+
+    /// How much of its speed a wounded miner keeps, down to half when near death.
+    pub fn speed_factor(&self) -> f32 {
+        (0.5 + 0.5 * self.current() / self.max).max(0.5).min(1.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wounds_bleed_then_heal() {
+        let mut health = Health::new(100.0);
+        health.hurt(Cause::Combat, 10.0);
+        assert!(health.is_bleeding());
+        health.update(10.0, false, false);
+        let bled = health.current();
+        assert!(bled < 90.0);
+        for _ in 0..120 {
+            health.update(1.0, false, false);
+        }
+        assert!(!health.is_bleeding());
+        let closed = health.current();
+        health.update(10.0, false, false);
+        assert!(health.current() > closed);
+        // resting heals faster
+        let mut rested = Health::new(100.0);
+        rested.injuries = health.injuries.clone();
+        health.update(10.0, false, false);
+        rested.update(10.0, true, false);
+        assert!(rested.current() > health.current());
+    }
+
+    #[test]
+    fn deprivation_adds_up() {
+        let mut health = Health::new(100.0);
+        health.hurt(Cause::Deprivation, 30.0);
+        health.hurt(Cause::Deprivation, 30.0);
+        health.hurt(Cause::Fall, 0.0);
+        assert_eq!(health.injuries.len(), 1);
+        assert!(!health.is_bleeding());
+        health.hurt(Cause::Fall, 20.0);
+        assert_eq!(health.worst(), Some(Cause::Deprivation));
+        assert_eq!(health.wounds(), vec![(Cause::Fall, 20.0), (Cause::Deprivation, 60.0)]);
+        assert_eq!(health.speed_factor(), 0.6);
+        health.hurt(Cause::CaveIn, 20.0);
+        assert!(health.is_dead());
+    }
+
+    #[test]
+    fn slow_healing() {
+        let mut health = Health::new(100.0);
+        health.hurt(Cause::Deprivation, 10.0);
+        health.hurt(Cause::Fire, 10.0);
+        assert!(!health.is_bleeding());
+        // the body doesn't mend while it still goes without, and burns take longer than anything else
+        health.update(10.0, false, true);
+        assert_eq!(health.wounds(), vec![(Cause::Fire, 9.5), (Cause::Deprivation, 10.0)]);
+        health.update(10.0, false, false);
+        assert_eq!(health.wounds(), vec![(Cause::Fire, 9.0), (Cause::Deprivation, 9.0)]);
+    }
+}
@@ -0,0 +1,420 @@
+use std::collections::{BinaryHeap, HashMap, HashSet};
+use pathfinding;
+use pathfinding::{Bounds, Node};
+use tiles::{Tiles, TileEvent};
+
+/// Width and height of a cluster, in cells
+const CLUSTER_SIZE: usize = 16;
+/// Entrances longer than this get a transition at both ends instead of one in the middle
+const LONG_ENTRANCE: usize = 6;
+
+/// How the abstract search got from one cell to the next
+#[derive(Copy, Clone, PartialEq)]
+enum Edge {
+    /// from the start to a transition of its cluster
+    Start,
+    /// between two transitions of a cluster
+    Intra,
+    /// across a cluster border
+    Inter,
+    /// from a transition to the goal in its cluster
+    Goal,
+}
+
+/// Hierarchical pathfinding (HPA*) over clusters of the map.
+///
+/// Cells next to a cluster border on both sides of an entrance are transitions.
+/// Routes between the transitions of a cluster are cached, so a long route is
+/// found in the small graph of transitions and then stitched from cached pieces.
+pub struct Hpa {
+    pub clusters_x: usize,
+    pub clusters_y: usize,
+    /// Transition cells of every cluster
+    nodes: Vec<Vec<(usize, usize)>>,
+    /// Cheapest route between two transitions of the same cluster and its cost
+    intra: HashMap<((usize, usize), (usize, usize)), (f32, Vec<(usize, usize)>)>,
+    /// Transitions across a border, reachable from a transition in one step
+    inter: HashMap<(usize, usize), Vec<((usize, usize), f32)>>,
+    /// Last known cost of every cell, to skip changes that don't affect walking
+    costs: Vec<Option<f32>>,
+}
+
+impl Hpa {
+    pub fn new(tiles: &Tiles) -> Hpa {
+        let clusters_x = (tiles.width + CLUSTER_SIZE - 1) / CLUSTER_SIZE;
+        let clusters_y = (tiles.height + CLUSTER_SIZE - 1) / CLUSTER_SIZE;
+        let mut hpa = Hpa {
+            clusters_x: clusters_x,
+            clusters_y: clusters_y,
+            nodes: vec![Vec::new(); clusters_x * clusters_y],
+            intra: HashMap::new(),
+            inter: HashMap::new(),
+            costs: tiles.ground.iter().map(|&id| pathfinding::tile_cost(tiles.tiles[id].tex_id)).collect(),
+        };
+        for cx in 0..clusters_x {
+            for cy in 0..clusters_y {
+                if cx + 1 < clusters_x {
+                    hpa.build_entrances(tiles, (cx, cy), (cx + 1, cy));
+                }
+                if cy + 1 < clusters_y {
+                    hpa.build_entrances(tiles, (cx, cy), (cx, cy + 1));
+                }
+            }
+        }
+        for cx in 0..clusters_x {
+            for cy in 0..clusters_y {
+                hpa.build_cluster(tiles, (cx, cy));
+            }
+        }
+        println!("Abstract graph: {} clusters, {} transitions, {} routes",
+                 clusters_x * clusters_y, hpa.inter.len(), hpa.intra.len());
+        hpa
+    }
+
+    pub fn cluster_of(&self, cell: (usize, usize)) -> (usize, usize) {
+        (cell.0 / CLUSTER_SIZE, cell.1 / CLUSTER_SIZE)
+    }
+
+    fn cluster_index(&self, cluster: (usize, usize)) -> usize {
+        cluster.0 * self.clusters_y + cluster.1
+    }
+
+    fn bounds(&self, tiles: &Tiles, cluster: (usize, usize)) -> Bounds {
+        let (x0, y0) = (cluster.0 * CLUSTER_SIZE, cluster.1 * CLUSTER_SIZE);
+        ((x0, y0), ((x0 + CLUSTER_SIZE).min(tiles.width), (y0 + CLUSTER_SIZE).min(tiles.height)))
+    }
+
+    /// Pairs of cells facing each other across the border of two neighbouring clusters.
+    fn border(&self, tiles: &Tiles, a: (usize, usize), b: (usize, usize)) -> Vec<((usize, usize), (usize, usize))> {
+        let ((x0, y0), (x1, y1)) = self.bounds(tiles, a);
+        if b.0 > a.0 {
+            (y0..y1).map(|y| ((x1 - 1, y), (x1, y))).collect()
+        } else {
+            (x0..x1).map(|x| ((x, y1 - 1), (x, y1))).collect()
+        }
+    }
+
+    /// Finds the entrances on the border of two neighbouring clusters and adds their transitions.
+    fn build_entrances(&mut self, tiles: &Tiles, a: (usize, usize), b: (usize, usize)) {
+        let border = self.border(tiles, a, b);
+        let mut run: Vec<((usize, usize), (usize, usize))> = Vec::new();
+        for i in 0..(border.len() + 1) {
+            let open = i < border.len() && {
+                let (from, to) = border[i];
+                self.cell_cost(tiles, from).is_some() && self.cell_cost(tiles, to).is_some()
+            };
+            if open {
+                run.push(border[i]);
+                continue;
+            }
+            if run.len() > LONG_ENTRANCE {
+                let (first, last) = (run[0], run[run.len() - 1]);
+                self.add_transition(tiles, first);
+                self.add_transition(tiles, last);
+            } else if run.len() > 0 {
+                let middle = run[run.len() / 2];
+                self.add_transition(tiles, middle);
+            }
+            run.clear();
+        }
+    }
+
+    fn cell_cost(&self, tiles: &Tiles, cell: (usize, usize)) -> Option<f32> {
+        self.costs[tiles.cell_index(cell)]
+    }
+
+    fn add_transition(&mut self, tiles: &Tiles, (a, b): ((usize, usize), (usize, usize))) {
+        let (cost_a, cost_b) = (self.cell_cost(tiles, a).unwrap(), self.cell_cost(tiles, b).unwrap());
+        self.inter.entry(a).or_insert_with(Vec::new).push((b, cost_b));
+        self.inter.entry(b).or_insert_with(Vec::new).push((a, cost_a));
+    }
+
+    /// Drops the transitions on the border of two neighbouring clusters.
+    fn clear_entrances(&mut self, tiles: &Tiles, a: (usize, usize), b: (usize, usize)) {
+        for (from, to) in self.border(tiles, a, b) {
+            for &(cell, other) in [(from, to), (to, from)].iter() {
+                let is_empty = match self.inter.get_mut(&cell) {
+                    Some(partners) => {
+                        partners.retain(|&(p, _)| p != other);
+                        partners.is_empty()
+                    },
+                    None => false,
+                };
+                if is_empty {
+                    self.inter.remove(&cell);
+                }
+            }
+        }
+    }
+
+    /// Collects the transitions of a cluster and caches the routes between them.
+    fn build_cluster(&mut self, tiles: &Tiles, cluster: (usize, usize)) {
+        let bounds = self.bounds(tiles, cluster);
+        let cluster_idx = self.cluster_index(cluster);
+        for &node in self.nodes[cluster_idx].iter() {
+            for &other in self.nodes[cluster_idx].iter() {
+                self.intra.remove(&(node, other));
+            }
+        }
+        let nodes = self.inter.keys()
+            .filter(|&&cell| pathfinding::in_bounds(bounds, cell))
+            .map(|&cell| cell)
+            .collect::<Vec<_>>();
+        for &node in nodes.iter() {
+            let search = pathfinding::search(tiles, node, None, bounds);
+            for &other in nodes.iter() {
+                if other == node {
+                    continue;
+                }
+                if let Some(path) = search.path_to(other) {
+                    self.intra.insert((node, other), (search.cost(other).unwrap(), path));
+                }
+            }
+        }
+        self.nodes[cluster_idx] = nodes;
+    }
+
+    /// Rebuilds the entrances of a cluster and the cached routes of it and its neighbours.
+    fn rebuild(&mut self, tiles: &Tiles, cluster: (usize, usize)) {
+        let mut neighbours = Vec::new();
+        if cluster.0 > 0 {
+            neighbours.push(((cluster.0 - 1, cluster.1), cluster));
+        }
+        if cluster.1 > 0 {
+            neighbours.push(((cluster.0, cluster.1 - 1), cluster));
+        }
+        if cluster.0 + 1 < self.clusters_x {
+            neighbours.push((cluster, (cluster.0 + 1, cluster.1)));
+        }
+        if cluster.1 + 1 < self.clusters_y {
+            neighbours.push((cluster, (cluster.0, cluster.1 + 1)));
+        }
+        for &(a, b) in neighbours.iter() {
+            self.clear_entrances(tiles, a, b);
+            self.build_entrances(tiles, a, b);
+        }
+        self.build_cluster(tiles, cluster);
+        for &(a, b) in neighbours.iter() {
+            self.build_cluster(tiles, if a == cluster { b } else { a });
+        }
+    }
+
+    /// Updates the clusters whose cells got cheaper, more expensive, blocked or opened.
+    pub fn handle_events(&mut self, tiles: &Tiles, events: &[TileEvent]) {
+        let mut dirty = HashSet::new();
+        for event in events.iter() {
+            let id = match *event {
+                TileEvent::Changed(id) => id,
+                _ => continue,
+            };
+            let cell = match tiles.cell_at(tiles.tiles[id].position) {
+                Some(cell) => cell,
+                None => continue,
+            };
+            let cell_idx = tiles.cell_index(cell);
+            if tiles.ground[cell_idx] != id {
+                continue;
+            }
+            let cost = pathfinding::tile_cost(tiles.tiles[id].tex_id);
+            if self.costs[cell_idx] != cost {
+                self.costs[cell_idx] = cost;
+                dirty.insert(self.cluster_of(cell));
+            }
+        }
+        for cluster in dirty {
+            self.rebuild(tiles, cluster);
+        }
+    }
+
+    /// Finds a route between two cells, excluding `from` and ending with `to`.
+    ///
+    /// The route is only near optimal: it goes through transitions of the clusters.
+    pub fn find_path(&self, tiles: &Tiles, from: (usize, usize), to: (usize, usize)) -> Option<Vec<(usize, usize)>> {
+        if self.cell_cost(tiles, to).is_none() {
+            return None;
+        }
+        let (from_cluster, to_cluster) = (self.cluster_of(from), self.cluster_of(to));
+        if from_cluster == to_cluster {
+            let path = pathfinding::find_path_within(tiles, from, to, self.bounds(tiles, from_cluster));
+            if path.is_some() {
+                return path;
+            }
+        }
+
+        // connect both ends to the transitions of their clusters, unless they are transitions already
+        let from_idx = self.cluster_index(from_cluster);
+        let to_idx = self.cluster_index(to_cluster);
+        let start = if self.nodes[from_idx].contains(&from) {
+            None
+        } else {
+            Some(pathfinding::search_to_all(tiles, from, &self.nodes[from_idx], self.bounds(tiles, from_cluster)))
+        };
+        let mut goal_routes = HashMap::new();
+        if !self.nodes[to_idx].contains(&to) {
+            // one search back from the goal serves all the transitions of its cluster
+            let goal = pathfinding::search_back_from_all(tiles, to, &self.nodes[to_idx], self.bounds(tiles, to_cluster));
+            for &node in self.nodes[to_idx].iter() {
+                if let Some(mut route) = goal.path_to(node) {
+                    route.pop();
+                    route.reverse();
+                    route.push(to);
+                    let cost = pathfinding::route_cost(tiles, node, &route);
+                    goal_routes.insert(node, (cost, route));
+                }
+            }
+        }
+
+        // search the abstract graph
+        let mut costs: HashMap<(usize, usize), f32> = HashMap::new();
+        let mut came_from: HashMap<(usize, usize), ((usize, usize), Edge)> = HashMap::new();
+        let mut open = BinaryHeap::new();
+        costs.insert(from, 0.0);
+        open.push(Node { estimate: pathfinding::heuristic(from, to), cell_idx: tiles.cell_index(from) });
+        while let Some(Node { estimate, cell_idx }) = open.pop() {
+            let cell = (cell_idx / tiles.height, cell_idx % tiles.height);
+            if cell == to {
+                return Some(self.refine(from, to, &came_from, &start, &goal_routes));
+            }
+            let cost = costs[&cell];
+            if estimate > cost + pathfinding::heuristic(cell, to) {
+                continue;
+            }
+            let mut edges = Vec::new();
+            if cell == from && start.is_some() {
+                for &node in self.nodes[from_idx].iter() {
+                    if let Some(node_cost) = start.as_ref().unwrap().cost(node) {
+                        edges.push((node, node_cost, Edge::Start));
+                    }
+                }
+            } else {
+                let cluster_idx = self.cluster_index(self.cluster_of(cell));
+                for &node in self.nodes[cluster_idx].iter() {
+                    if let Some(&(node_cost, _)) = self.intra.get(&(cell, node)) {
+                        edges.push((node, node_cost, Edge::Intra));
+                    }
+                }
+                if let Some(&(goal_cost, _)) = goal_routes.get(&cell) {
+                    edges.push((to, goal_cost, Edge::Goal));
+                }
+            }
+            if let Some(partners) = self.inter.get(&cell) {
+                edges.extend(partners.iter().map(|&(partner, partner_cost)| (partner, partner_cost, Edge::Inter)));
+            }
+            for (next, edge_cost, edge) in edges {
+                let next_cost = cost + edge_cost;
+                if costs.get(&next).map_or(true, |&c| next_cost < c) {
+                    costs.insert(next, next_cost);
+                    came_from.insert(next, (cell, edge));
+                    open.push(Node {
+                        estimate: next_cost + pathfinding::heuristic(next, to),
+                        cell_idx: tiles.cell_index(next),
+                    });
+                }
+            }
+        }
+        None
+    }
+
+    /// Stitches the cell by cell route from the transitions the abstract search went through.
+    fn refine(&self, from: (usize, usize), to: (usize, usize),
+              came_from: &HashMap<(usize, usize), ((usize, usize), Edge)>,
+              start: &Option<pathfinding::Search>,
+              goal_routes: &HashMap<(usize, usize), (f32, Vec<(usize, usize)>)>) -> Vec<(usize, usize)> {
+        let mut steps = Vec::new();
+        let mut current = to;
+        while current != from {
+            let (previous, edge) = came_from[&current];
+            steps.push((previous, current, edge));
+            current = previous;
+        }
+        steps.reverse();
+        let mut path = Vec::new();
+        for (a, b, edge) in steps {
+            match edge {
+                Edge::Start => path.extend(start.as_ref().unwrap().path_to(b).unwrap()),
+                Edge::Intra => path.extend(self.intra[&(a, b)].1.iter().cloned()),
+                Edge::Inter => path.push(b),
+                Edge::Goal => path.extend(goal_routes[&a].1.iter().cloned()),
+            }
+        }
+        path
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A map of three by three clusters split by a wall with a single gap in it.
+    fn walled() -> Tiles {
+        let rows = (0..40).map(|y| (0..40).map(|x| match (x, y) {
+            (20, 30) => '.',
+            (20, _) => '#',
+            (x, _) if x % 7 == 3 => ',',
+            _ => '.',
+        }).collect::<String>()).collect::<Vec<_>>();
+        Tiles::from_rows(&rows.iter().map(|row| row.as_str()).collect::<Vec<_>>())
+    }
+
+    /// Checks that a route is made of steps between neighbours and returns its cost.
+    fn walk(tiles: &Tiles, from: (usize, usize), path: &[(usize, usize)]) -> f32 {
+        let mut previous = from;
+        for &cell in path.iter() {
+            assert!(pathfinding::neighbours(tiles, previous).iter().any(|&(next, _)| next == cell),
+                    "{:?} doesn't lead to {:?}", previous, cell);
+            previous = cell;
+        }
+        pathfinding::route_cost(tiles, from, path)
+    }
+
+    #[test]
+    fn near_optimal() {
+        let tiles = walled();
+        let hpa = Hpa::new(&tiles);
+        for &(from, to) in [((2, 2), (37, 2)), ((5, 39), (39, 0)), ((19, 10), (21, 10)), ((1, 1), (14, 12))].iter() {
+            let path = hpa.find_path(&tiles, from, to).unwrap();
+            assert_eq!(path.last(), Some(&to));
+            let best = pathfinding::find_path(&tiles, from, to).unwrap();
+            let (cost, best_cost) = (walk(&tiles, from, &path), pathfinding::route_cost(&tiles, from, &best));
+            assert!(cost <= best_cost * 1.25, "{:?} to {:?} costs {} instead of {}", from, to, cost, best_cost);
+        }
+    }
+
+    #[test]
+    fn between_transitions() {
+        let tiles = walled();
+        let hpa = Hpa::new(&tiles);
+        let (from, to) = (hpa.nodes[0][0], hpa.nodes[hpa.nodes.len() - 1][0]);
+        let path = hpa.find_path(&tiles, from, to).unwrap();
+        let best = pathfinding::find_path(&tiles, from, to).unwrap();
+        assert!(walk(&tiles, from, &path) <= pathfinding::route_cost(&tiles, from, &best) * 1.25);
+        let path = hpa.find_path(&tiles, (2, 2), to).unwrap();
+        assert_eq!(path.last(), Some(&to));
+        walk(&tiles, (2, 2), &path);
+    }
+
+    #[test]
+    fn same_cell_and_unreachable() {
+        let mut tiles = walled();
+        let mut hpa = Hpa::new(&tiles);
+        assert_eq!(hpa.find_path(&tiles, (4, 4), (4, 4)), Some(vec![]));
+        assert_eq!(hpa.find_path(&tiles, (4, 4), (20, 4)), None);
+        tiles.fill((20, 30), ::SPRITE_STONE);
+        let events = tiles.take_events();
+        hpa.handle_events(&tiles, &events);
+        assert_eq!(hpa.find_path(&tiles, (2, 2), (37, 2)), None);
+    }
+
+    #[test]
+    fn follows_changes() {
+        let mut tiles = walled();
+        let mut hpa = Hpa::new(&tiles);
+        let before = hpa.find_path(&tiles, (18, 2), (22, 2)).unwrap();
+        tiles.fill((20, 2), ::SPRITE_FLOOR);
+        let events = tiles.take_events();
+        hpa.handle_events(&tiles, &events);
+        let after = hpa.find_path(&tiles, (18, 2), (22, 2)).unwrap();
+        assert!(walk(&tiles, (18, 2), &after) < walk(&tiles, (18, 2), &before));
+        assert!(after.contains(&(20, 2)));
+    }
+}
@@ -0,0 +1,152 @@
+use rand;
+use rand::Rng;
+
+const ONSETS: [&'static str; 16] = ["b", "d", "g", "k", "th", "dr", "gr", "br", "m", "n", "r", "s", "st", "v", "z", "kh"];
+const VOWELS: [&'static str; 8] = ["a", "e", "i", "o", "u", "ai", "or", "um"];
+const CODAS: [&'static str; 10] = ["", "", "", "n", "r", "k", "d", "l", "m", "st"];
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Trait {
+    /// works slower and wants a rest sooner
+    Lazy,
+    /// walks further for a job and takes danger and death better
+    Brave,
+    /// eats early and minds a poor meal more
+    Greedy,
+}
+
+pub const TRAITS: [Trait; 3] = [Trait::Lazy, Trait::Brave, Trait::Greedy];
+
+/// Body of a miner, every attribute from 1 to 10
+#[derive(Copy, Clone, Debug)]
+pub struct Attributes {
+    /// how much it can carry
+    pub strength: u8,
+    /// how fast it walks
+    pub agility: u8,
+    /// how much of a beating it takes
+    pub toughness: u8,
+}
+
+/// Who a miner is.
+#[derive(Clone, Debug)]
+pub struct Identity {
+    pub name: String,
+    /// In years
+    pub age: u32,
+    pub attributes: Attributes,
+    pub traits: Vec<Trait>,
+}
+
+impl Identity {
+    /// Rolls a grown-up miner.
+    pub fn generate() -> Identity {
+        let mut rng = rand::thread_rng();
+        let traits = TRAITS.iter().filter(|_| rng.gen::<f32>() < 0.3).map(|&t| t).collect();
+        Identity {
+            name: generate_name(),
+            age: rng.gen_range(18, 70),
+            attributes: Attributes {
+                strength: rng.gen_range(1, 11),
+                agility: rng.gen_range(1, 11),
+                toughness: rng.gen_range(1, 11),
+            },
+            traits: traits,
+        }
+    }
+
+    /// A newborn taking after its parents, with the family name of the first.
+    pub fn born_to(a: &Identity, b: &Identity) -> Identity {
+        let mut rng = rand::thread_rng();
+        let family = a.name.split(' ').last().unwrap_or("");
+        let first = generate_name();
+        let first = first.split(' ').next().unwrap();
+        let mut inherit = |x: u8, y: u8| {
+            let average = (x as i32 + y as i32) / 2;
+            (average + rng.gen_range(-1, 2)).max(1).min(10) as u8
+        };
+        let attributes = Attributes {
+            strength: inherit(a.attributes.strength, b.attributes.strength),
+            agility: inherit(a.attributes.agility, b.attributes.agility),
+            toughness: inherit(a.attributes.toughness, b.attributes.toughness),
+        };
+        // a trait of either parent is passed on half the time
+        let traits = TRAITS.iter()
+            .filter(|&&t| (a.has(t) || b.has(t)) && rand::random::<f32>() < 0.5)
+            .map(|&t| t)
+            .collect();
+        Identity {
+            name: format!("{} {}", first, family),
+            age: 0,
+            attributes: attributes,
+            traits: traits,
+        }
+    }
+
+    pub fn has(&self, t: Trait) -> bool {
+        self.traits.contains(&t)
+    }
+}
+
+/// A first name of two or three syllables and a family name of two.
+pub fn generate_name() -> String {
+    let mut rng = rand::thread_rng();
+    let syllables = rng.gen_range(2, 4);
+    format!("{} {}", word(&mut rng, syllables), word(&mut rng, 2))
+}
+
+fn word<R: Rng>(rng: &mut R, syllables: usize) -> String {
+    let mut word = String::new();
+    for i in 0..syllables {
+        word.push_str(rng.choose(&ONSETS).unwrap());
+        word.push_str(rng.choose(&VOWELS).unwrap());
+        // only the last syllable may close on a consonant, to keep names pronounceable
+        if i == syllables - 1 {
+            word.push_str(rng.choose(&CODAS).unwrap());
+        }
+    }
+    let mut chars = word.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => word,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names() {
+        for _ in 0..100 {
+            let name = generate_name();
+            let words = name.split(' ').collect::<Vec<_>>();
+            assert_eq!(words.len(), 2);
+            for word in words {
+                assert!(word.len() >= 2);
+                assert!(word.chars().next().unwrap().is_uppercase());
+                assert!(word.chars().skip(1).all(|c| c.is_lowercase()));
+            }
+        }
+    }
+
+    #[test]
+    fn children_take_after_their_parents() {
+        let mut a = Identity::generate();
+        let mut b = Identity::generate();
+        a.attributes = Attributes { strength: 10, agility: 1, toughness: 4 };
+        b.attributes = Attributes { strength: 10, agility: 1, toughness: 8 };
+        a.traits = vec![Trait::Lazy];
+        b.traits = vec![];
+        for _ in 0..100 {
+            let child = Identity::born_to(&a, &b);
+            assert_eq!(child.age, 0);
+            assert_eq!(child.name.split(' ').last(), a.name.split(' ').last());
+            assert!(child.attributes.strength >= 9);
+            assert!(child.attributes.agility <= 2);
+            assert!(child.attributes.toughness >= 5 && child.attributes.toughness <= 7);
+            // only what a parent has can be passed on
+            assert!(child.traits.iter().all(|&t| t == Trait::Lazy));
+        }
+    }
+}
@@ -0,0 +1,136 @@
+use tiles::Tiles;
+
+/// Weight of an item of this kind, in kilograms
+pub fn weight(tex_id: u32) -> f32 {
+    match tex_id {
+        ::SPRITE_WOOD => 20.0,
+        ::SPRITE_ROCK => 30.0,
+        ::SPRITE_FOOD => 1.0,
+        ::SPRITE_DRINK => 30.0,
+        _ => 5.0,
+    }
+}
+
+/// Room an item of this kind takes, in litres
+pub fn volume(tex_id: u32) -> f32 {
+    match tex_id {
+        ::SPRITE_WOOD => 40.0,
+        ::SPRITE_ROCK => 15.0,
+        ::SPRITE_FOOD => 2.0,
+        ::SPRITE_DRINK => 30.0,
+        _ => 10.0,
+    }
+}
+
+/// Items someone carries around, limited by how heavy and how bulky they are together.
+pub struct Inventory {
+    pub items: Vec<usize>,
+    pub max_weight: f32,
+    pub max_volume: f32,
+}
+
+impl Inventory {
+    pub fn new(max_weight: f32, max_volume: f32) -> Inventory {
+        Inventory {
+            items: Vec::new(),
+            max_weight: max_weight,
+            max_volume: max_volume,
+        }
+    }
+
+    pub fn weight(&self, tiles: &Tiles) -> f32 {
+        self.items.iter().map(|&id| weight(tiles.tiles[id].tex_id)).sum()
+    }
+
+    pub fn volume(&self, tiles: &Tiles) -> f32 {
+        self.items.iter().map(|&id| volume(tiles.tiles[id].tex_id)).sum()
+    }
+
+    pub fn contains(&self, item: usize) -> bool {
+        self.items.contains(&item)
+    }
+
+    /// Whether the item would still fit in.
+    pub fn can_take(&self, tiles: &Tiles, item: usize) -> bool {
+        let tex_id = tiles.tiles[item].tex_id;
+        tiles.tiles[item].can_be_carried && !self.contains(item)
+            && self.weight(tiles) + weight(tex_id) <= self.max_weight
+            && self.volume(tiles) + volume(tex_id) <= self.max_volume
+    }
+
+    pub fn add(&mut self, item: usize) {
+        self.items.push(item);
+    }
+
+    pub fn remove(&mut self, item: usize) -> bool {
+        let count = self.items.len();
+        self.items.retain(|&id| id != item);
+        count != self.items.len()
+    }
+
+    /// How much of the usual speed is left with this load.
+    ///
+    /// Up to half the weight limit nothing changes, a full load halves the speed.
+    pub fn speed_factor(&self, tiles: &Tiles) -> f32 {
+        let load = self.weight(tiles) / self.max_weight;
+        if load <= 0.5 {
+            1.0
+        } else {
+            1.0 - (load.min(1.0) - 0.5)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tiles::Tile;
+
+    fn items(tiles: &mut Tiles, tex_ids: &[u32]) -> Vec<usize> {
+        tex_ids.iter().map(|&tex_id| {
+            let mut item = Tile::new(tiles.cell_position((0, 0)), tex_id, None);
+            item.can_be_carried = true;
+            tiles.spawn(item)
+        }).collect()
+    }
+
+    #[test]
+    fn weight_and_volume_limits() {
+        let mut tiles = Tiles::from_rows(&["."]);
+        let ids = items(&mut tiles, &[::SPRITE_ROCK, ::SPRITE_ROCK, ::SPRITE_WOOD, ::SPRITE_FOOD]);
+        let mut inventory = Inventory::new(70.0, 50.0);
+        assert!(inventory.can_take(&tiles, ids[0]));
+        inventory.add(ids[0]);
+        assert!(!inventory.can_take(&tiles, ids[0]));
+        inventory.add(ids[1]);
+        assert_eq!(inventory.weight(&tiles), 60.0);
+        assert_eq!(inventory.volume(&tiles), 30.0);
+        // too heavy and too bulky
+        assert!(!inventory.can_take(&tiles, ids[2]));
+        assert!(inventory.can_take(&tiles, ids[3]));
+        inventory.remove(ids[1]);
+        // light enough now, but doesn't fit in
+        assert!(!inventory.can_take(&tiles, ids[2]));
+        inventory.max_volume = 60.0;
+        assert!(inventory.can_take(&tiles, ids[2]));
+        // only loose items can be carried
+        tiles.tiles[ids[3]].can_be_carried = false;
+        assert!(!inventory.can_take(&tiles, ids[3]));
+        assert!(!inventory.remove(ids[3]));
+    }
+
+    #[test]
+    fn heavy_loads_slow_down() {
+        let mut tiles = Tiles::from_rows(&["."]);
+        let ids = items(&mut tiles, &[::SPRITE_WOOD, ::SPRITE_ROCK, ::SPRITE_ROCK]);
+        let mut inventory = Inventory::new(80.0, 100.0);
+        assert_eq!(inventory.speed_factor(&tiles), 1.0);
+        inventory.add(ids[0]);
+        assert_eq!(inventory.speed_factor(&tiles), 1.0);
+        inventory.add(ids[1]);
+        assert!((inventory.speed_factor(&tiles) - 0.875).abs() < 0.001);
+        inventory.add(ids[2]);
+        // over the limit is as slow as a full load
+        assert_eq!(inventory.speed_factor(&tiles), 0.5);
+    }
+}
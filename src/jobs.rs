@@ -0,0 +1,431 @@
+use std::collections::HashSet;
+use pathfinding::Search;
+use reservations::{Reservations, Target};
+use skills::Skill;
+use stockpiles::Stockpiles;
+use tiles;
+use tiles::{Tiles, TileEvent};
+
+/// Every kind of job, in the order they are listed to the player
+pub const JOB_KINDS: [JobKind; 9] = [JobKind::Chop, JobKind::Mine, JobKind::Gather, JobKind::Haul, JobKind::Build,
+                                     JobKind::Channel, JobKind::Smooth, JobKind::Fill, JobKind::Furnish];
+
+/// Priority of a job when the player didn't say, on a scale from 1 up to `MAX_PRIORITY`
+pub const DEFAULT_PRIORITY: u8 = 4;
+pub const MAX_PRIORITY: u8 = 7;
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum JobKind {
+    /// fell a tree into a log
+    Chop,
+    /// dig a stone wall out
+    Mine,
+    /// pick a plant clean
+    Gather,
+    /// carry a loose item to a stockpile
+    Haul,
+    /// put up a pillar holding up the ceiling
+    Build,
+    /// open a hole down to the level below
+    Channel,
+    /// smooth a rough floor
+    Smooth,
+    /// fill a hole back in, with the material given to the job
+    Fill,
+    /// put a bed up on an empty floor
+    Furnish,
+}
+
+impl JobKind {
+    /// Whether the job can be done to this tile.
+    pub fn fits(&self, tiles: &Tiles, tile_id: usize) -> bool {
+        let tile = &tiles.tiles[tile_id];
+        if tile.is_removed {
+            return false;
+        }
+        match *self {
+            JobKind::Chop => tile.tex_id == ::SPRITE_TREE,
+            JobKind::Mine => tile.tex_id == ::SPRITE_STONE,
+            JobKind::Gather => tile.tex_id == ::SPRITE_SHRUB,
+            JobKind::Haul => tile.can_be_carried,
+            JobKind::Build | JobKind::Channel | JobKind::Furnish => match ground_cell(tiles, tile_id) {
+                Some(cell) => tiles::is_walkable(tile.tex_id) && tiles.items_at(cell).len() == 0,
+                None => false,
+            },
+            JobKind::Smooth => tile.tex_id == ::SPRITE_FLOOR,
+            JobKind::Fill => tile.tex_id == ::SPRITE_HOLE,
+        }
+    }
+
+    /// Seconds the job takes an untrained miner once there.
+    pub fn duration(&self) -> f32 {
+        match *self {
+            JobKind::Chop => 6.0,
+            JobKind::Mine => 8.0,
+            JobKind::Gather => 3.0,
+            JobKind::Haul => 0.0,
+            JobKind::Build => 10.0,
+            JobKind::Channel => 8.0,
+            JobKind::Smooth => 5.0,
+            JobKind::Fill => 6.0,
+            JobKind::Furnish => 8.0,
+        }
+    }
+
+    /// The skill that speeds the job up and that it trains.
+    pub fn skill(&self) -> Option<Skill> {
+        match *self {
+            JobKind::Chop => Some(Skill::Woodcutting),
+            JobKind::Mine => Some(Skill::Mining),
+            JobKind::Haul => Some(Skill::Hauling),
+            JobKind::Build | JobKind::Smooth | JobKind::Furnish => Some(Skill::Building),
+            JobKind::Channel => Some(Skill::Mining),
+            JobKind::Gather | JobKind::Fill => None,
+        }
+    }
+
+    /// Whether the job is done from a neighbouring cell rather than on the cell itself.
+    pub fn is_done_from_next_cell(&self) -> bool {
+        match *self {
+            JobKind::Mine | JobKind::Build | JobKind::Channel | JobKind::Fill => true,
+            _ => false,
+        }
+    }
+}
+
+/// The cell a tile is the ground of, if it is.
+fn ground_cell(tiles: &Tiles, tile_id: usize) -> Option<(usize, usize)> {
+    tiles.cell_at(tiles.tiles[tile_id].position).filter(|&cell| tiles.ground[tiles.cell_index(cell)] == tile_id)
+}
+
+#[derive(Debug)]
+pub struct Job {
+    pub id: usize,
+    pub kind: JobKind,
+    pub cell: (usize, usize),
+    /// The tree, the wall, the plant, the item or the floor the job is about
+    pub tile: usize,
+    pub priority: u8,
+    /// Ground a fill lays down
+    pub material: Option<u32>,
+}
+
+impl Job {
+    /// Cost of walking from the origin of a search to where the job is done, `None` if it can't be reached.
+    pub fn cost_from(&self, search: &Search) -> Option<f32> {
+        if !self.kind.is_done_from_next_cell() {
+            return search.cost(self.cell);
+        }
+        let mut best: Option<f32> = None;
+        for dx in -1..2 {
+            for dy in -1..2 {
+                let (x, y) = (self.cell.0 as isize + dx, self.cell.1 as isize + dy);
+                if (dx, dy) == (0, 0) || x < 0 || y < 0 {
+                    continue;
+                }
+                let cost = search.cost((x as usize, y as usize));
+                if cost.is_some() && best.map_or(true, |b| cost.unwrap() < b) {
+                    best = cost;
+                }
+            }
+        }
+        best
+    }
+}
+
+/// Work designated by the player, waiting for a miner or being done.
+pub struct Jobs {
+    pub queue: Vec<Job>,
+    /// Who is doing which job and on which tiles
+    pub reservations: Reservations,
+    next_id: usize,
+}
+
+impl Jobs {
+    pub fn new() -> Jobs {
+        Jobs {
+            queue: Vec::new(),
+            reservations: Reservations::new(),
+            next_id: 0,
+        }
+    }
+
+    pub fn get(&self, id: usize) -> Option<&Job> {
+        self.queue.iter().find(|job| job.id == id)
+    }
+
+    fn push(&mut self, kind: JobKind, cell: (usize, usize), tile: usize, priority: u8) {
+        self.queue.push(Job {
+            id: self.next_id,
+            kind: kind,
+            cell: cell,
+            tile: tile,
+            priority: priority,
+            material: None,
+        });
+        self.next_id += 1;
+    }
+
+    /// Queues a job for every discovered tile on the cells it fits.
+    ///
+    /// Returns the number of new jobs.
+    pub fn designate(&mut self, tiles: &Tiles, cells: &[(usize, usize)], kind: JobKind, priority: u8) -> usize {
+        let queued = self.queue.iter().map(|job| job.tile).collect::<HashSet<_>>();
+        let mut count = 0;
+        for &cell in cells.iter() {
+            for tile_id in tiles.resources_at(cell, true) {
+                if kind.fits(tiles, tile_id) && !queued.contains(&tile_id) {
+                    self.push(kind, cell, tile_id, priority);
+                    count += 1;
+                }
+            }
+        }
+        println!("Designated {} {:?} jobs with priority {}", count, kind, priority);
+        count
+    }
+
+    /// Queues filling the discovered holes on the cells with a material.
+    ///
+    /// Returns the number of new jobs.
+    pub fn designate_fill(&mut self, tiles: &Tiles, cells: &[(usize, usize)], material: u32, priority: u8) -> usize {
+        let first = self.queue.len();
+        let count = self.designate(tiles, cells, JobKind::Fill, priority);
+        for job in self.queue[first..].iter_mut() {
+            job.material = Some(material);
+        }
+        count
+    }
+
+    /// Changes the priority of the jobs on the cells.
+    ///
+    /// Returns the number of jobs changed.
+    pub fn set_priority(&mut self, cells: &[(usize, usize)], priority: u8) -> usize {
+        let priority = priority.max(1).min(MAX_PRIORITY);
+        let mut count = 0;
+        for job in self.queue.iter_mut().filter(|job| cells.contains(&job.cell)) {
+            job.priority = priority;
+            count += 1;
+        }
+        println!("Set priority {} on {} jobs", priority, count);
+        count
+    }
+
+    /// Drops the jobs on the cells. Miners doing them notice and stop.
+    ///
+    /// Returns the number of cancelled jobs.
+    pub fn cancel_at(&mut self, cells: &[(usize, usize)]) -> usize {
+        let count = self.queue.len();
+        self.queue.retain(|job| !cells.contains(&job.cell));
+        println!("Cancelled {} jobs", count - self.queue.len());
+        count - self.queue.len()
+    }
+
+    /// Whether a job is free for the miner to take.
+    fn is_free(&self, job: &Job, miner_id: usize) -> bool {
+        !self.reservations.is_taken(Target::Job(job.id), miner_id)
+            && !self.reservations.is_taken(Target::Tile(job.tile), miner_id)
+    }
+
+    /// Whether any job of the kinds is free for the miner to take. Jobs in `skip` are left out.
+    pub fn any_free(&self, miner_id: usize, kinds: &[JobKind], skip: &[usize]) -> bool {
+        self.queue.iter().any(|job| kinds.contains(&job.kind) && !skip.contains(&job.id) && self.is_free(job, miner_id))
+    }
+
+    /// Up to `count` free jobs of the kinds the miner can walk to from the origin of the search,
+    /// with the cost of the walk, closest first. Jobs in `skip` are left out.
+    ///
+    /// Only jobs of the highest priority among them are returned, however far they are.
+    pub fn nearest_free(&self, miner_id: usize, search: &Search, kinds: &[JobKind], skip: &[usize],
+                        count: usize) -> Vec<(usize, f32)> {
+        let free = self.queue.iter()
+            .filter(|job| kinds.contains(&job.kind) && !skip.contains(&job.id) && self.is_free(job, miner_id))
+            .filter_map(|job| job.cost_from(search).map(|cost| (job, cost)))
+            .collect::<Vec<_>>();
+        let top = free.iter().map(|&(job, _)| job.priority).max().unwrap_or(0);
+        let mut free = free.iter()
+            .filter(|&&(job, _)| job.priority == top)
+            .map(|&(job, cost)| (job.id, cost))
+            .collect::<Vec<_>>();
+        free.sort_by(|a, b| a.1.partial_cmp(&b.1).unwrap());
+        free.truncate(count);
+        free
+    }
+
+    /// Hands a free job over to a miner and reserves it and its tile.
+    pub fn claim(&mut self, id: usize, miner_id: usize) -> bool {
+        let tile = match self.get(id) {
+            Some(job) if self.is_free(job, miner_id) => job.tile,
+            _ => return false,
+        };
+        self.reservations.reserve(Target::Job(id), miner_id);
+        self.reservations.reserve(Target::Tile(tile), miner_id);
+        true
+    }
+
+    /// Puts a job back into the queue for someone else to take.
+    pub fn unclaim(&mut self, id: usize) {
+        let tile = self.get(id).map(|job| job.tile);
+        self.reservations.release(Target::Job(id));
+        if tile.is_some() {
+            self.reservations.release(Target::Tile(tile.unwrap()));
+        }
+    }
+
+    pub fn complete(&mut self, id: usize) {
+        self.unclaim(id);
+        self.queue.retain(|job| job.id != id);
+    }
+
+    /// Queues hauling for the loose items among the tiles that aren't on a stockpile taking them.
+    pub fn queue_hauls<I: IntoIterator<Item = usize>>(&mut self, tiles: &Tiles, ids: I, stockpiles: &Stockpiles) {
+        let queued = self.queue.iter().map(|job| job.tile).collect::<HashSet<_>>();
+        for id in ids {
+            if JobKind::Haul.fits(tiles, id) && !stockpiles.is_stored(tiles, id) && !queued.contains(&id) {
+                let cell = tiles.cell_at(tiles.tiles[id].position);
+                if cell.is_some() {
+                    self.push(JobKind::Haul, cell.unwrap(), id, DEFAULT_PRIORITY);
+                }
+            }
+        }
+    }
+
+    /// Drops the jobs whose tile is gone or changed into something they don't fit,
+    /// moves hauling along with its item and queues hauling for the items that moved or showed up.
+    pub fn handle_events(&mut self, tiles: &Tiles, events: &[TileEvent], stockpiles: &Stockpiles) {
+        let changed = events.iter().map(|event| match *event {
+            TileEvent::Changed(id) | TileEvent::Spawned(id) | TileEvent::Removed(id) => id,
+        }).collect::<HashSet<_>>();
+        self.queue.retain(|job| !changed.contains(&job.tile) || job.kind.fits(tiles, job.tile));
+        // an item dropped on the way is picked up again where it lies
+        for job in self.queue.iter_mut().filter(|job| job.kind == JobKind::Haul && changed.contains(&job.tile)) {
+            if let Some(cell) = tiles.cell_at(tiles.tiles[job.tile].position) {
+                job.cell = cell;
+            }
+        }
+        self.queue_hauls(tiles, changed, stockpiles);
+        self.reservations.handle_events(events);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pathfinding;
+
+    /// A map split by a wall, with every cell discovered but the bottom right one.
+    fn split() -> Tiles {
+        let mut tiles = Tiles::from_rows(&[
+            "...#..",
+            "...#.#",
+            "...#..",
+        ]);
+        for tile in tiles.tiles.iter_mut() {
+            tile.is_discovered = true;
+        }
+        let ground = tiles.ground[tiles.cell_index((5, 2))];
+        tiles.tiles[ground].is_discovered = false;
+        tiles
+    }
+
+    fn everywhere(tiles: &Tiles) -> Vec<(usize, usize)> {
+        (0..tiles.width).flat_map(|x| (0..tiles.height).map(move |y| (x, y))).collect()
+    }
+
+    #[test]
+    fn designate_and_cancel() {
+        let tiles = split();
+        let mut jobs = Jobs::new();
+        assert_eq!(jobs.designate(&tiles, &everywhere(&tiles), JobKind::Mine, DEFAULT_PRIORITY), 4);
+        // nothing is queued twice
+        assert_eq!(jobs.designate(&tiles, &everywhere(&tiles), JobKind::Mine, DEFAULT_PRIORITY), 0);
+        // undiscovered cells are left alone
+        assert_eq!(jobs.designate(&tiles, &[(5, 2)], JobKind::Build, DEFAULT_PRIORITY), 0);
+        assert_eq!(jobs.designate(&tiles, &[(4, 2)], JobKind::Build, DEFAULT_PRIORITY), 1);
+        assert_eq!(jobs.cancel_at(&[(3, 0), (3, 1), (0, 0)]), 2);
+        assert_eq!(jobs.queue.len(), 3);
+        assert!(jobs.queue.iter().all(|job| job.cell != (3, 0) && job.cell != (3, 1)));
+    }
+
+    #[test]
+    fn claim_and_unclaim() {
+        let tiles = split();
+        let mut jobs = Jobs::new();
+        jobs.designate(&tiles, &[(3, 2)], JobKind::Mine, DEFAULT_PRIORITY);
+        let id = jobs.queue[0].id;
+        assert!(jobs.claim(id, 1));
+        assert!(jobs.claim(id, 1));
+        assert!(!jobs.claim(id, 2));
+        jobs.unclaim(id);
+        assert!(jobs.claim(id, 2));
+        jobs.complete(id);
+        assert!(jobs.get(id).is_none());
+        assert!(!jobs.claim(id, 1));
+    }
+
+    #[test]
+    fn claims_what_can_be_walked_to() {
+        let tiles = split();
+        let mut jobs = Jobs::new();
+        jobs.designate(&tiles, &[(5, 1), (3, 2)], JobKind::Mine, DEFAULT_PRIORITY);
+        let search = pathfinding::search(&tiles, (0, 0), None, pathfinding::map_bounds(&tiles));
+        // the far wall can't be got to from the left side
+        let nearest = jobs.nearest_free(1, &search, &[JobKind::Mine], &[], 5);
+        assert_eq!(nearest.len(), 1);
+        let claimed = nearest[0].0;
+        assert_eq!(jobs.get(claimed).unwrap().cell, (3, 2));
+        assert!(jobs.claim(claimed, 1));
+        assert!(jobs.nearest_free(2, &search, &[JobKind::Mine], &[], 5).is_empty());
+        assert!(jobs.nearest_free(1, &search, &[JobKind::Chop], &[], 5).is_empty());
+        // from the right side it can, the other one is taken
+        let search = pathfinding::search(&tiles, (4, 0), None, pathfinding::map_bounds(&tiles));
+        assert_eq!(jobs.nearest_free(2, &search, &[JobKind::Mine], &[], 5).len(), 1);
+    }
+
+    #[test]
+    fn priority_falls_back_to_what_can_be_reached() {
+        let tiles = split();
+        let mut jobs = Jobs::new();
+        jobs.designate(&tiles, &[(5, 1)], JobKind::Mine, 7);
+        jobs.designate(&tiles, &[(3, 2)], JobKind::Mine, 4);
+        // the urgent wall is out of reach, so the lesser one is next
+        let search = pathfinding::search(&tiles, (0, 0), None, pathfinding::map_bounds(&tiles));
+        let nearest = jobs.nearest_free(1, &search, &[JobKind::Mine], &[], 5);
+        assert_eq!(nearest.len(), 1);
+        assert_eq!(jobs.get(nearest[0].0).unwrap().cell, (3, 2));
+        // where both can be walked to, only the urgent one is offered
+        let search = pathfinding::search(&tiles, (4, 0), None, pathfinding::map_bounds(&tiles));
+        let nearest = jobs.nearest_free(1, &search, &[JobKind::Mine], &[], 5);
+        assert_eq!(nearest.len(), 1);
+        assert_eq!(jobs.get(nearest[0].0).unwrap().cell, (5, 1));
+    }
+
+    #[test]
+    fn hauls_follow_their_items() {
+        let mut tiles = split();
+        let mut jobs = Jobs::new();
+        let mut stockpiles = Stockpiles::new();
+        stockpiles.designate(&tiles, &[(0, 0)], vec![::RESOURCE_STONE], 2);
+        let rock = tiles.dig((3, 0)).unwrap();
+        let events = tiles.take_events();
+        jobs.handle_events(&tiles, &events, &stockpiles);
+        assert_eq!(jobs.queue.len(), 1);
+        assert_eq!((jobs.queue[0].kind, jobs.queue[0].cell, jobs.queue[0].tile), (JobKind::Haul, (3, 0), rock));
+        // carrying it around says nothing, dropping it on the way moves the job along
+        tiles.pick_up(rock);
+        tiles.carry(rock, tiles.cell_position((2, 0)));
+        tiles.carry(rock, tiles.cell_position((1, 1)));
+        tiles.put_down(rock, (1, 1));
+        let events = tiles.take_events();
+        assert_eq!(events.len(), 2);
+        jobs.handle_events(&tiles, &events, &stockpiles);
+        assert_eq!(jobs.queue.len(), 1);
+        assert_eq!(jobs.queue[0].cell, (1, 1));
+        // a stored item needs no hauling
+        jobs.complete(jobs.queue[0].id);
+        tiles.pick_up(rock);
+        tiles.put_down(rock, (0, 0));
+        let events = tiles.take_events();
+        jobs.handle_events(&tiles, &events, &stockpiles);
+        assert!(jobs.queue.is_empty());
+    }
+}
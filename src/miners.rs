@@ -1,133 +1,1229 @@
+use std::str::FromStr;
+use behaviour;
+use behaviour::{Agent, Node, Status};
 use tiles;
+use hpa;
+use health::{Cause, Health};
+use identity::{Identity, Trait};
+use inventory::Inventory;
+use mood::{Mood, ThoughtKind};
+use flowfield;
+use jobs;
+use movement::{MovementState, Walker};
+use needs;
+use needs::{Need, Needs};
+use pathfinding;
+use reservations::Target;
+use stockpiles;
+use skills::Skills;
+use social;
+use social::{Bond, Relationships};
+use utility;
+use utility::Candidate;
 use rand;
 use cgmath::Vector2;
 use cgmath::prelude::*;
 
-#[derive(Copy, Clone, PartialEq)]
-pub enum MovementState {
-    Moving,
-    Idle,
-}
+/// Experience gained per job done
+const JOB_XP: f32 = 10.0;
+
+/// Jobs weighed against each other when choosing what to do next, the closest ones
+const JOB_CANDIDATES: usize = 8;
+/// Needs whose meter is above this aren't worth considering yet
+const NEED_CONSIDERED: f32 = 0.75;
+
+/// Seconds before a miner whose need can't be met looks for a way to meet it again
+const NEED_CHECK_DELAY: f32 = 10.0;
+/// Seconds before a miner tries the jobs it couldn't get to again
+const UNREACHABLE_RETRY: f32 = 30.0;
+/// Damage taken falling into a hole
+const FALL_DAMAGE: f32 = 20.0;
+/// How close in cells miners have to be to keep each other company
+const COMPANY_RANGE: f32 = 2.5;
+/// How fast miners get to know each other, per second: just being around, chatting on a break, sharing a meal
+const MET_CLOSENESS: f32 = 0.2;
+const CHAT_CLOSENESS: f32 = 2.0;
+const MEAL_CLOSENESS: f32 = 4.0;
 
 #[derive(Copy, Clone, PartialEq)]
 pub enum State {
     Idle,
+    /// walking to the job it claimed
+    GoingToJob,
     CuttingTree,
+    Mining,
+    Gathering,
+    Building,
+    /// carrying an item to a stockpile
+    Hauling,
+    GoingToEat,
+    Eating,
+    GoingToDrink,
+    Drinking,
+    /// walking to the bed it claimed
+    GoingToBed,
+    /// in the bed it is working on, or else on the floor
+    Sleeping,
+}
+
+impl State {
+    /// The need a miner in this state is looking after.
+    pub fn tending(&self) -> Option<Need> {
+        match *self {
+            State::GoingToEat | State::Eating => Some(Need::Food),
+            State::GoingToDrink | State::Drinking => Some(Need::Water),
+            State::GoingToBed | State::Sleeping => Some(Need::Rest),
+            _ => None,
+        }
+    }
 }
 
 pub struct Miner {
+    pub id: usize,
+    pub identity: Identity,
     pub movement_state: MovementState,
     pub state: State,
     pub tile: tiles::Tile,
     pub waypoints: Vec<Vector2<f32>>,
-    pub speed: f32,
-    pub state_counter: u32,
+    /// Distance walked per second
+    pub move_speed: f32,
+    /// How far the miner can see, in cells
+    pub sight_radius: f32,
+    pub health: Health,
+    pub skills: Skills,
+    pub needs: Needs,
+    pub mood: Mood,
+    /// Seconds until the miner looks for food, water or sleep again
+    pub need_check: f32,
+    /// Seconds of work left on the current job
+    pub work_left: f32,
     pub working_on: Option<usize>,
+    pub job: Option<usize>,
+    /// Kinds of jobs the miner takes on
+    pub labours: Vec<jobs::JobKind>,
+    /// What the miner carries around
+    pub inventory: Inventory,
+    /// Item of the inventory being taken to a stockpile
+    pub hauling: Option<usize>,
+    /// Stockpile cell the hauled item goes to
+    pub haul_to: Option<(usize, usize)>,
+    /// Jobs the miner found no route or room for, skipped until it finishes some other job or a while passes
+    pub unreachable_jobs: Vec<usize>,
+    /// Seconds until the unreachable jobs are tried again
+    pub unreachable_retry: f32,
+    /// Shared destination followed through a flow field instead of an own route
+    pub flow_target: Option<(usize, usize)>,
 }
 
 pub struct Miners {
     pub miners: Vec<Miner>,
+    /// What every miner does, loaded from `assets/miner.bt`
+    pub behaviour: Node<Action>,
+    /// Who knows whom among the living
+    pub relationships: Relationships,
+    /// Id the next miner to arrive gets
+    pub next_id: usize,
 }
 
 impl Miner {
     pub fn new(
+        id: usize,
         position: Vector2<f32>,
         tex_id: u32,
+        identity: Identity,
     ) -> Miner {
+        // the body sets how fast, strong and hardy the miner is
+        let attributes = identity.attributes;
+        // a miner is always in sight of itself
+        let mut tile = tiles::Tile::new(position, tex_id, None);
+        tile.is_discovered = true;
+        tile.is_visible = true;
         Miner {
-            tile: tiles::Tile::new(position, tex_id, None),
+            id: id,
+            tile: tile,
             movement_state: MovementState::Idle,
             state: State::Idle,
             waypoints: Vec::new(),
-            speed: 10.0,
-            state_counter: 0,
+            move_speed: 8.0 + 0.4 * attributes.agility as f32,
+            sight_radius: 6.0,
+            health: Health::new(80.0 + 4.0 * attributes.toughness as f32),
+            skills: Skills::new(),
+            needs: Needs::new(),
+            mood: Mood::new(),
+            need_check: 0.0,
+            work_left: 0.0,
             working_on: None,
+            job: None,
+            // children don't work
+            labours: if identity.age >= social::ADULT_AGE { jobs::JOB_KINDS.to_vec() } else { Vec::new() },
+            inventory: Inventory::new(30.0 + 4.0 * attributes.strength as f32, 60.0),
+            hauling: None,
+            haul_to: None,
+            unreachable_jobs: Vec::new(),
+            unreachable_retry: 0.0,
+            flow_target: None,
+            identity: identity,
         }
     }
 }
 
+impl Walker for Miner {
+    fn position(&self) -> Vector2<f32> {
+        self.tile.position
+    }
+
+    fn set_position(&mut self, position: Vector2<f32>) {
+        self.tile.position = position;
+    }
+
+    fn waypoints(&mut self) -> &mut Vec<Vector2<f32>> {
+        &mut self.waypoints
+    }
+}
+
+impl Miner {
+    /// Plans a route to where a job is done from: its cell, or the closest reachable cell next to it.
+    pub fn route_to_job(&mut self, job: &jobs::Job, tiles: &tiles::Tiles, hpa: &hpa::Hpa) -> bool {
+        if !job.kind.is_done_from_next_cell() {
+            return self.route_to(tiles.cell_position(job.cell), tiles, hpa);
+        }
+        let cell = match tiles.cell_at(self.tile.position) {
+            Some(cell) => cell,
+            None => return false,
+        };
+        let mut sides = pathfinding::neighbours(tiles, job.cell).iter().map(|&(side, _)| side).collect::<Vec<_>>();
+        sides.sort_by(|&a, &b| pathfinding::heuristic(cell, a).partial_cmp(&pathfinding::heuristic(cell, b)).unwrap());
+        for side in sides {
+            if self.route_to(tiles.cell_position(side), tiles, hpa) {
+                return true;
+            }
+        }
+        false
+    }
+
+    /// The need the miner drops everything for, if any. A lazy one wants a rest early.
+    pub fn urgent_need(&self) -> Option<Need> {
+        self.needs.most_urgent(self.identity.has(Trait::Lazy))
+    }
+
+    pub fn does(&self, kind: jobs::JobKind) -> bool {
+        self.labours.contains(&kind)
+    }
+
+    /// Lets the miner take on a kind of job or not. A job of a kind it no longer does is dropped.
+    pub fn set_labour(&mut self, kind: jobs::JobKind, enabled: bool, jobs: &mut jobs::Jobs, tiles: &mut tiles::Tiles) {
+        if enabled && !self.does(kind) {
+            self.labours.push(kind);
+        } else if !enabled {
+            self.labours.retain(|&labour| labour != kind);
+            if self.job.and_then(|id| jobs.get(id)).map_or(false, |job| job.kind == kind) {
+                self.state = self.stop_working(jobs, tiles);
+            }
+        }
+        println!("{} does {:?}", self.identity.name, self.labours);
+    }
+
+    /// Takes an item lying around into the inventory, if there is room for it.
+    pub fn pick_up(&mut self, tiles: &mut tiles::Tiles, item: usize) -> bool {
+        if !self.inventory.can_take(tiles, item) {
+            return false;
+        }
+        tiles.pick_up(item);
+        tiles.carry(item, self.tile.position);
+        self.inventory.add(item);
+        true
+    }
+
+    /// Puts an item of the inventory down on a cell.
+    pub fn drop_at(&mut self, tiles: &mut tiles::Tiles, item: usize, cell: (usize, usize)) -> bool {
+        if !self.inventory.remove(item) {
+            return false;
+        }
+        tiles.put_down(item, cell);
+        if self.hauling == Some(item) {
+            self.hauling = None;
+        }
+        true
+    }
+
+    /// Puts an item of the inventory down where the miner stands.
+    pub fn drop(&mut self, tiles: &mut tiles::Tiles, item: usize) -> bool {
+        match tiles.cell_at(self.tile.position) {
+            Some(cell) => self.drop_at(tiles, item, cell),
+            None => false,
+        }
+    }
+
+    /// Gives an item to a miner standing next to this one, if it has room for it.
+    pub fn hand_over(&mut self, other: &mut Miner, tiles: &mut tiles::Tiles, item: usize) -> bool {
+        let near = match (tiles.cell_at(self.tile.position), tiles.cell_at(other.tile.position)) {
+            (Some(a), Some(b)) => pathfinding::heuristic(a, b) < 2.0,
+            _ => false,
+        };
+        if !near || !self.inventory.contains(item) || !other.inventory.can_take(tiles, item) {
+            return false;
+        }
+        self.inventory.remove(item);
+        if self.hauling == Some(item) {
+            self.hauling = None;
+        }
+        other.inventory.add(item);
+        tiles.carry(item, other.tile.position);
+        true
+    }
+
+    /// Drops the current job, everything the miner reserved for it and the item it was hauling.
+    fn stop_working(&mut self, jobs: &mut jobs::Jobs, tiles: &mut tiles::Tiles) -> State {
+        if self.hauling.is_some() {
+            let item = self.hauling.unwrap();
+            self.drop(tiles, item);
+        }
+        jobs.reservations.release_all(self.id);
+        // whatever was taken of a meal or a drink is finished at once
+        let (meal, draught) = (self.needs.meal, self.needs.draught);
+        self.needs.satisfy(Need::Food, meal);
+        self.needs.satisfy(Need::Water, draught);
+        self.needs.meal = 0.0;
+        self.needs.draught = 0.0;
+        self.hauling = None;
+        self.haul_to = None;
+        self.job = None;
+        self.working_on = None;
+        self.waypoints.clear();
+        State::Idle
+    }
+
+    /// Skips a job for a while, e.g. one it can't get to or whose item has nowhere to go.
+    fn give_up_on(&mut self, job_id: usize) {
+        if self.unreachable_jobs.len() == 0 {
+            self.unreachable_retry = UNREACHABLE_RETRY;
+        }
+        self.unreachable_jobs.push(job_id);
+    }
+
+    /// Gains experience in the skill a job trains.
+    fn train(&mut self, kind: jobs::JobKind) {
+        let skill = match kind.skill() {
+            Some(skill) => skill,
+            None => return,
+        };
+        let level = self.skills.gain(skill, JOB_XP);
+        if level.is_some() {
+            println!("{} reached level {} in {:?}", self.identity.name, level.unwrap(), skill);
+            self.mood.add(ThoughtKind::LearnedSomething, &self.identity);
+        }
+    }
+
+    /// How much of its speed the miner keeps under its load.
+    fn load_factor(&self, tiles: &tiles::Tiles) -> f32 {
+        1.0 - (1.0 - self.inventory.speed_factor(tiles)) * self.skills.load_factor()
+    }
+
+    /// Drops everything to walk over to food, drink or water, or to a bed or else to lie down where it is.
+    ///
+    /// Returns the new state, `None` when the need can't be met from here.
+    fn look_after(&mut self, need: Need, tiles: &mut tiles::Tiles, jobs: &mut jobs::Jobs,
+                  stockpiles: &stockpiles::Stockpiles) -> Option<State> {
+        let cell = match tiles.cell_at(self.tile.position) {
+            Some(cell) => cell,
+            None => return None,
+        };
+        let search = needs::reachable_from(tiles, cell);
+        let target = match need {
+            Need::Food => needs::find_food(tiles, stockpiles, &jobs.reservations, self.id, &search)
+                .map(|(cell, item)| (cell, Some(item))),
+            Need::Water => needs::find_drink_or_water(tiles, stockpiles, &jobs.reservations, self.id, &search),
+            Need::Rest => match needs::find_bed(tiles, &jobs.reservations, self.id, &search) {
+                Some((cell, bed)) => Some((cell, Some(bed))),
+                None => {
+                    self.stop_working(jobs, tiles);
+                    println!("{} lies down to sleep", self.identity.name);
+                    return Some(State::Sleeping);
+                },
+            },
+        };
+        target.map(|(target, item)| {
+            self.stop_working(jobs, tiles);
+            self.flow_target = None;
+            self.follow_path(&search.path_to(target).unwrap(), tiles);
+            println!("{} goes for {:?} at {:?}", self.identity.name, need, target);
+            if item.is_some() {
+                jobs.reservations.reserve(Target::Tile(item.unwrap()), self.id);
+                self.working_on = item;
+            }
+            match need {
+                Need::Food => State::GoingToEat,
+                Need::Water => State::GoingToDrink,
+                Need::Rest => State::GoingToBed,
+            }
+        })
+    }
+}
+
 impl Miners {
-    pub fn new(count: u8, tiles: &tiles::Tiles) -> Miners {
+    /// Places the first miners, or fails when their behaviour can't be loaded.
+    pub fn new(count: u8, tiles: &tiles::Tiles) -> Result<Miners, String> {
         let mut miners = Vec::new();
-        for tile in tiles.get_random_walkable(count) {
-            miners.push(Miner::new(tile.position, ::SPRITE_MINER));
+        for (id, tile) in tiles.get_random_walkable(count).iter().enumerate() {
+            miners.push(Miner::new(id, tile.position, ::SPRITE_MINER, Identity::generate()));
         }
-        Miners {
+        let behaviour = behaviour::load("miner.bt")
+            .map_err(|error| format!("Can't load the miner behaviour: {}", error))?;
+        Ok(Miners {
             miners: miners,
-        }
+            behaviour: behaviour,
+            relationships: Relationships::new(),
+            next_id: count as usize,
+        })
+    }
+
+    /// Adds a miner, a migrant or a newborn, and returns its id.
+    pub fn arrive(&mut self, position: Vector2<f32>, identity: Identity) -> usize {
+        let id = self.next_id;
+        self.next_id += 1;
+        println!("{} ({}) joined the colony", identity.name, identity.age);
+        self.miners.push(Miner::new(id, position, ::SPRITE_MINER, identity));
+        id
     }
 
     pub fn get_tiles(&mut self) -> Vec<&tiles::Tile> {
         self.miners.iter().map(|miner| &miner.tile).collect::<Vec<_>>()
     }
 
-    pub fn update(&mut self, duration: f32, tiles: &mut tiles::Tiles) {
+    pub fn get_viewers(&self) -> Vec<(Vector2<f32>, f32)> {
+        self.miners.iter().map(|miner| (miner.tile.position, miner.sight_radius)).collect::<Vec<_>>()
+    }
+
+    /// Hurts every miner standing on one of the cells.
+    pub fn hurt_at(&mut self, cells: &[(usize, usize)], cause: Cause, damage: f32, tiles: &tiles::Tiles) {
+        let hurt = self.miners.iter()
+            .filter(|miner| tiles.cell_at(miner.tile.position).map_or(false, |cell| cells.contains(&cell)))
+            .map(|miner| miner.id)
+            .collect::<Vec<_>>();
+        for id in hurt {
+            self.hurt(id, cause, damage);
+        }
+    }
+
+    /// Miners out on cells without a roof get wet.
+    pub fn rain_on(&mut self, tiles: &tiles::Tiles) {
         for miner in self.miners.iter_mut() {
-            miner.state = match miner.state {
-                State::Idle => {
-                    let closest_tile = tiles.resource_at(miner.tile.position);
-                    if closest_tile.is_some() {
-                        let r = closest_tile.unwrap();
-                        if r.resource_id.map_or(false, |x| x == ::RESOURCE_WOOD) && r.resource_count > 0 && !r.can_be_carried {
-                            miner.state_counter = miner.speed as u32;
-                            miner.working_on = tiles.index_of(&r);
-                            State::CuttingTree
-                        } else {
-                            State::Idle
-                        }
-                    } else {
-                        State::Idle
+            let roofed = tiles.cell_at(miner.tile.position).map_or(true, |cell| tiles.roofed[tiles.cell_index(cell)]);
+            if !roofed && !miner.mood.remembers(ThoughtKind::RainedOn) {
+                miner.mood.add(ThoughtKind::RainedOn, &miner.identity);
+            }
+        }
+    }
+
+    /// Wounds a miner. Its friends hear of it.
+    pub fn hurt(&mut self, id: usize, cause: Cause, damage: f32) {
+        {
+            let miner = match self.get_mut(id) {
+                Some(miner) => miner,
+                None => return,
+            };
+            miner.health.hurt(cause, damage);
+            if cause == Cause::CaveIn {
+                miner.mood.add(ThoughtKind::CaughtInCaveIn, &miner.identity);
+            }
+            println!("{} got hurt by {:?}, health {:.0}", miner.identity.name, cause, miner.health.current());
+        }
+        self.tell_relations(id, ThoughtKind::FriendHurt, ThoughtKind::FriendHurt);
+    }
+
+    /// Gives the friends and the spouse of a miner a thought about something that happened to it.
+    fn tell_relations(&mut self, id: usize, friend: ThoughtKind, spouse: ThoughtKind) {
+        for (other, relationship) in self.relationships.of(id) {
+            let thought = match relationship.bond {
+                Bond::Friend => friend,
+                Bond::Spouse => spouse,
+                _ => continue,
+            };
+            if let Some(miner) = self.get_mut(other) {
+                miner.mood.add(thought, &miner.identity);
+            }
+        }
+    }
+
+    /// Miners close to each other get to know each other, the more so when chatting on a break or eating together,
+    /// and cheer up or sour at the company of friends or rivals.
+    fn socialise(&mut self, duration: f32, tiles: &tiles::Tiles) {
+        let present = self.miners.iter()
+            .filter_map(|miner| tiles.cell_at(miner.tile.position).map(|cell| (miner.id, cell, miner.state,
+                                                                               miner.identity.age)))
+            .collect::<Vec<_>>();
+        let on_break = |state: State| state == State::Idle || (state.tending().is_some() && state != State::Sleeping);
+        for (i, &(a, cell_a, state_a, age_a)) in present.iter().enumerate() {
+            for &(b, cell_b, state_b, age_b) in present[i + 1..].iter() {
+                if pathfinding::heuristic(cell_a, cell_b) > COMPANY_RANGE {
+                    continue;
+                }
+                let closeness = if state_a == State::Eating && state_b == State::Eating {
+                    MEAL_CLOSENESS
+                } else if on_break(state_a) && on_break(state_b) {
+                    CHAT_CLOSENESS
+                } else {
+                    MET_CLOSENESS
+                };
+                let changed = self.relationships.interact(a, b, closeness, duration);
+                let names = (self.get(a).unwrap().identity.name.clone(), self.get(b).unwrap().identity.name.clone());
+                if changed.is_some() {
+                    println!("{} and {} are now {:?}", names.0, names.1, changed.unwrap());
+                }
+                if closeness > MET_CLOSENESS && self.relationships.court((a, age_a), (b, age_b), duration) {
+                    println!("{} and {} got married", names.0, names.1);
+                    for &id in [a, b].iter() {
+                        let miner = self.get_mut(id).unwrap();
+                        miner.mood.add(ThoughtKind::GotMarried, &miner.identity);
                     }
-                },
-                State::CuttingTree => {
-                    if miner.state_counter > 0 {
-                        miner.state_counter -= 1;
-                        State::CuttingTree
-                    } else {
-                        tiles.replace(miner.working_on, ::SPRITE_WOOD, true);
-                        miner.working_on = None;
-                        State::Idle
+                }
+                let thought = match self.relationships.bond(a, b) {
+                    Some(Bond::Friend) | Some(Bond::Spouse) => ThoughtKind::WithFriend,
+                    Some(Bond::Rival) => ThoughtKind::WithRival,
+                    _ => continue,
+                };
+                for &id in [a, b].iter() {
+                    let miner = self.get_mut(id).unwrap();
+                    if !miner.mood.remembers(thought) {
+                        miner.mood.add(thought, &miner.identity);
                     }
                 }
+            }
+        }
+    }
+
+    pub fn get(&self, id: usize) -> Option<&Miner> {
+        self.miners.iter().find(|miner| miner.id == id)
+    }
+
+    pub fn get_mut(&mut self, id: usize) -> Option<&mut Miner> {
+        self.miners.iter_mut().find(|miner| miner.id == id)
+    }
+
+    /// Id of the miner closest to a position, if one is within the distance.
+    pub fn miner_at(&self, position: Vector2<f32>, distance: f32) -> Option<usize> {
+        let mut best: Option<(f32, usize)> = None;
+        for miner in self.miners.iter() {
+            let d = (miner.tile.position - position).magnitude();
+            if d <= distance && best.map_or(true, |(b, _)| d < b) {
+                best = Some((d, miner.id));
+            }
+        }
+        best.map(|(_, id)| id)
+    }
+
+    /// Switches a kind of job on or off for a miner. Returns whether it is on now.
+    pub fn toggle_labour(&mut self, id: usize, kind: jobs::JobKind, jobs: &mut jobs::Jobs,
+                         tiles: &mut tiles::Tiles) -> Option<bool> {
+        self.get_mut(id).map(|miner| {
+            let enabled = !miner.does(kind);
+            miner.set_labour(kind, enabled, jobs, tiles);
+            enabled
+        })
+    }
+
+    /// Passes an item from one miner to another, both given by id, see `Miner::hand_over`.
+    pub fn hand_over(&mut self, from: usize, to: usize, tiles: &mut tiles::Tiles, item: usize) -> bool {
+        let (from, to) = {
+            let index = |id: usize| self.miners.iter().position(|miner| miner.id == id);
+            match (index(from), index(to)) {
+                (Some(from), Some(to)) if from != to => (from, to),
+                _ => return false,
+            }
+        };
+        let (low, high) = self.miners.split_at_mut(from.max(to));
+        let (a, b) = (&mut low[from.min(to)], &mut high[0]);
+        if from < to { a.hand_over(b, tiles, item) } else { b.hand_over(a, tiles, item) }
+    }
+
+    /// A hauler with an urgent need hands its load over to an idle hauler next to it,
+    /// who takes the job and the room on the stockpile over and carries on.
+    fn pass_on_hauls(&mut self, tiles: &mut tiles::Tiles, jobs: &mut jobs::Jobs, hpa: &hpa::Hpa) {
+        let mut handovers: Vec<(usize, usize, usize, (usize, usize))> = Vec::new();
+        for giver in self.miners.iter().filter(|miner| miner.state == State::Hauling && miner.urgent_need().is_some()) {
+            let (item, haul_to) = match (giver.hauling, giver.haul_to) {
+                (Some(item), Some(haul_to)) => (item, haul_to),
+                _ => continue,
             };
-            miner.movement_state = match miner.state {
-                State::Idle => {
-                    if miner.waypoints.len() < 1 {
-                        if rand::random::<f32>() < 0.2 {
-                            let next_waypoint = tiles.get_closest_walkable(miner.tile.position);
-                            if next_waypoint.is_some() {
-                                miner.waypoints.push(next_waypoint.unwrap().position);
-                                println!("New waypoint: from {:?} to {:?}",
-                                         miner.tile.position, miner.waypoints[miner.waypoints.len() - 1]);
-                                MovementState::Moving
-                            } else {
-                                MovementState::Idle
-                            }
-                        } else {
-                            MovementState::Idle
-                        }
-                    } else if (miner.tile.position - miner.waypoints[miner.waypoints.len() - 1]).magnitude() < 2.0 {
-                        miner.waypoints.pop();
-                        MovementState::Idle
-                    } else {
-                        miner.movement_state
+            let cell = tiles.cell_at(giver.tile.position);
+            let taker = self.miners.iter().find(|other| {
+                other.state == State::Idle && other.does(jobs::JobKind::Haul) && other.inventory.can_take(tiles, item)
+                    && !handovers.iter().any(|&(_, to, _, _)| to == other.id)
+                    && match (cell, tiles.cell_at(other.tile.position)) {
+                        (Some(a), Some(b)) => pathfinding::heuristic(a, b) < 2.0,
+                        _ => false,
                     }
+            });
+            if let Some(taker) = taker {
+                handovers.push((giver.id, taker.id, item, haul_to));
+            }
+        }
+        for (from, to, item, haul_to) in handovers {
+            if !self.hand_over(from, to, tiles, item) {
+                continue;
+            }
+            jobs.reservations.hand_over(from, to);
+            let job = {
+                let giver = self.get_mut(from).unwrap();
+                let job = giver.job.take();
+                giver.state = giver.stop_working(jobs, tiles);
+                job
+            };
+            let taker = self.get_mut(to).unwrap();
+            taker.job = job;
+            taker.hauling = Some(item);
+            taker.haul_to = Some(haul_to);
+            taker.state = if taker.route_to(tiles.cell_position(haul_to), tiles, hpa) {
+                println!("{} took a load over to {:?}", taker.identity.name, haul_to);
+                State::Hauling
+            } else {
+                taker.stop_working(jobs, tiles)
+            };
+        }
+    }
+
+    /// Sends every miner to the same cell, interrupting whatever they were doing.
+    pub fn gather_at(&mut self, cell: (usize, usize), jobs: &mut jobs::Jobs, tiles: &mut tiles::Tiles) {
+        for miner in self.miners.iter_mut() {
+            miner.state = miner.stop_working(jobs, tiles);
+            miner.flow_target = Some(cell);
+        }
+    }
+
+    pub fn update(&mut self, duration: f32, tiles: &mut tiles::Tiles, jobs: &mut jobs::Jobs,
+                  stockpiles: &stockpiles::Stockpiles, hpa: &mut hpa::Hpa, flow_fields: &mut flowfield::FlowFields) {
+        let mut deaths = Vec::new();
+        for miner in self.miners.iter_mut().filter(|miner| miner.health.is_dead()) {
+            // let go of the job, the claims and everything carried
+            miner.stop_working(jobs, tiles);
+            for item in miner.inventory.items.clone() {
+                miner.drop(tiles, item);
+            }
+            let cell = tiles.cell_at(miner.tile.position);
+            if let Some(cell) = cell {
+                let mut corpse = tiles::Tile::new(tiles.cell_position(cell), ::SPRITE_CORPSE, None);
+                corpse.can_be_carried = true;
+                tiles.spawn(corpse);
+            }
+            match miner.health.worst() {
+                Some(cause) => println!("{} died of {:?}", miner.identity.name, cause),
+                None => println!("{} died", miner.identity.name),
+            }
+            deaths.push((miner.id, cell));
+        }
+        self.miners.retain(|miner| !miner.health.is_dead());
+        for &(id, _) in deaths.iter() {
+            self.tell_relations(id, ThoughtKind::FriendDied, ThoughtKind::SpouseDied);
+            self.relationships.forget(id);
+        }
+        // whoever sees someone die remembers it
+        for miner in self.miners.iter_mut() {
+            let cell = match tiles.cell_at(miner.tile.position) {
+                Some(cell) => cell,
+                None => continue,
+            };
+            let witnessed = deaths.iter()
+                .filter_map(|&(_, cell)| cell)
+                .filter(|&(x, y)| {
+                    let (dx, dy) = (x as f32 - cell.0 as f32, y as f32 - cell.1 as f32);
+                    (dx * dx + dy * dy).sqrt() <= miner.sight_radius
+                })
+                .count();
+            for _ in 0..witnessed {
+                miner.mood.add(ThoughtKind::SawDeath, &miner.identity);
+            }
+        }
+
+        self.pass_on_hauls(tiles, jobs, hpa);
+        for miner in self.miners.iter_mut() {
+            let tending = miner.state.tending();
+            let deprivation = miner.needs.update(duration, tending);
+            miner.health.hurt(Cause::Deprivation, deprivation);
+            miner.health.update(duration, miner.state == State::Sleeping, deprivation > 0.0);
+            // the ground gave way under the miner, or a pillar went up where it stood:
+            // it ends up on the nearest solid ground
+            let ground = tiles.cell_at(miner.tile.position).map(|cell| tiles.ground_at(cell).tex_id);
+            if ground.map_or(false, |tex_id| !tiles::is_walkable(tex_id)) {
+                let landing = tiles.get_closest_walkable(miner.tile.position).map(|tile| tile.position);
+                if landing.is_some() {
+                    miner.tile.position = landing.unwrap();
+                    miner.waypoints.clear();
+                    if ground == Some(::SPRITE_HOLE) {
+                        miner.health.hurt(Cause::Fall, FALL_DAMAGE);
+                        println!("{} fell, health {:.0}", miner.identity.name, miner.health.current());
+                    }
+                }
+            }
+            miner.need_check -= duration;
+            // routes open up and stockpiles get room, so try again now and then
+            miner.unreachable_retry -= duration;
+            if miner.unreachable_retry <= 0.0 {
+                miner.unreachable_jobs.clear();
+            }
+            if miner.needs.food <= 0.0 && !miner.mood.remembers(ThoughtKind::WentHungry) {
+                miner.mood.add(ThoughtKind::WentHungry, &miner.identity);
+            }
+            if miner.needs.water <= 0.0 && !miner.mood.remembers(ThoughtKind::WentThirsty) {
+                miner.mood.add(ThoughtKind::WentThirsty, &miner.identity);
+            }
+            if miner.mood.update(duration) {
+                println!("{} broke down, mood {:.0}", miner.identity.name, miner.mood.score());
+                miner.state = miner.stop_working(jobs, tiles);
+                miner.flow_target = None;
+            }
+
+            {
+                let mut world = MinerWorld {
+                    miner: miner,
+                    duration: duration,
+                    tiles: tiles,
+                    jobs: jobs,
+                    stockpiles: stockpiles,
+                    hpa: hpa,
+                    flow_fields: flow_fields,
+                };
+                self.behaviour.run(&mut world);
+            }
+
+            let speed = miner.move_speed * miner.load_factor(tiles) * miner.needs.speed_factor()
+                * miner.health.speed_factor();
+            miner.movement_state = miner.walk(speed * duration);
+            if miner.movement_state == MovementState::Moving {
+                for &item in miner.inventory.items.iter() {
+                    tiles.carry(item, miner.tile.position);
+                }
+            }
+        }
+        self.socialise(duration, tiles);
+    }
+}
+
+/// The leaves of the miner behaviour tree, named as in `assets/miner.bt`
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Action {
+    /// in the middle of eating, drinking or sleeping
+    IsTendingNeed,
+    TendNeed,
+    /// some need is urgent and it's time to look for a way to meet it
+    HasUrgentNeed,
+    LookAfterNeed,
+    HasJob,
+    /// walks to the job, works on it and finishes it
+    DoJob,
+    /// too unhappy to do anything but wander about
+    IsBrokenDown,
+    /// heading for a meeting point
+    IsRallying,
+    Rally,
+    /// weighs needs and jobs against each other and starts on the best
+    Choose,
+    Wander,
+}
+
+/// What a miner can choose to do next
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Choice {
+    Need(Need),
+    Job(usize),
+}
+
+impl FromStr for Action {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Action, String> {
+        match name {
+            "is-tending-need" => Ok(Action::IsTendingNeed),
+            "tend-need" => Ok(Action::TendNeed),
+            "has-urgent-need" => Ok(Action::HasUrgentNeed),
+            "look-after-need" => Ok(Action::LookAfterNeed),
+            "has-job" => Ok(Action::HasJob),
+            "do-job" => Ok(Action::DoJob),
+            "is-broken-down" => Ok(Action::IsBrokenDown),
+            "is-rallying" => Ok(Action::IsRallying),
+            "rally" => Ok(Action::Rally),
+            "choose" => Ok(Action::Choose),
+            "wander" => Ok(Action::Wander),
+            _ => Err(format!("Unknown miner action '{}'", name)),
+        }
+    }
+}
+
+fn check(condition: bool) -> Status {
+    if condition { Status::Success } else { Status::Failure }
+}
+
+/// A miner and the parts of the world its behaviour acts on during one update.
+pub struct MinerWorld<'a> {
+    pub miner: &'a mut Miner,
+    pub duration: f32,
+    pub tiles: &'a mut tiles::Tiles,
+    pub jobs: &'a mut jobs::Jobs,
+    pub stockpiles: &'a stockpiles::Stockpiles,
+    pub hpa: &'a mut hpa::Hpa,
+    pub flow_fields: &'a mut flowfield::FlowFields,
+}
+
+impl<'a> Agent<Action> for MinerWorld<'a> {
+    fn run(&mut self, action: &Action) -> Status {
+        match *action {
+            Action::IsTendingNeed => check(self.miner.state.tending().is_some()),
+            Action::TendNeed => self.tend_need(),
+            Action::HasUrgentNeed => check(self.miner.state.tending().is_none() && self.miner.need_check <= 0.0
+                                           && self.miner.urgent_need().is_some()),
+            Action::LookAfterNeed => {
+                self.miner.need_check = NEED_CHECK_DELAY;
+                let need = self.miner.urgent_need().unwrap();
+                match self.miner.look_after(need, self.tiles, self.jobs, self.stockpiles) {
+                    Some(state) => {
+                        self.miner.state = state;
+                        Status::Success
+                    },
+                    None => Status::Failure,
+                }
+            },
+            Action::HasJob => check(self.miner.job.is_some()),
+            Action::DoJob => self.do_job(),
+            Action::IsBrokenDown => check(self.miner.mood.is_broken_down()),
+            Action::IsRallying => check(self.miner.flow_target.is_some()),
+            Action::Rally => self.rally(),
+            Action::Choose => self.choose(),
+            Action::Wander => self.wander(),
+        }
+    }
+}
+
+impl<'a> MinerWorld<'a> {
+    /// Gets going on a job the miner claimed: finds room on a stockpile for hauling and a route.
+    ///
+    /// Gives the job back and remembers it as unreachable if either is missing.
+    fn take_job(&mut self, job_id: usize) -> bool {
+        let (miner, tiles, jobs) = (&mut *self.miner, &mut *self.tiles, &mut *self.jobs);
+        let (kind, job_cell, item) = {
+            let job = jobs.get(job_id).unwrap();
+            (job.kind, job.cell, job.tile)
+        };
+        if kind == jobs::JobKind::Haul {
+            // only go for the item if some stockpile has room for it
+            let search = needs::reachable_from(tiles, job_cell);
+            let haul_to = self.stockpiles.find_free(tiles, &jobs.reservations, miner.id, &search, item);
+            if haul_to.is_some() {
+                jobs.reservations.reserve(haul_to.unwrap().1, miner.id);
+            }
+            miner.haul_to = haul_to.map(|(cell, _)| cell);
+        }
+        let has_room = kind != jobs::JobKind::Haul
+            || (miner.haul_to.is_some() && miner.inventory.can_take(tiles, item));
+        if has_room && miner.route_to_job(jobs.get(job_id).unwrap(), tiles, self.hpa) {
+            println!("{} took {:?}", miner.identity.name, jobs.get(job_id).unwrap());
+            miner.job = Some(job_id);
+            miner.state = State::GoingToJob;
+            return true;
+        }
+        jobs.unclaim(job_id);
+        jobs.reservations.release_all(miner.id);
+        miner.haul_to = None;
+        miner.give_up_on(job_id);
+        false
+    }
+
+    /// Scores looking after every need that is getting low and the jobs nearby, and starts on the best.
+    fn choose(&mut self) -> Status {
+        let cell = match self.tiles.cell_at(self.miner.tile.position) {
+            Some(cell) => cell,
+            None => return Status::Failure,
+        };
+        let mut candidates = Vec::new();
+        let low_needs = [Need::Food, Need::Water, Need::Rest].iter()
+            .map(|&need| (need, self.miner.needs.get(need)))
+            .filter(|&(_, meter)| meter < NEED_CONSIDERED)
+            .collect::<Vec<_>>();
+        // looking around for food and water is costly, don't do it on every update
+        let checks_needs = self.miner.need_check <= 0.0 && low_needs.len() > 0;
+        let has_jobs = self.jobs.any_free(self.miner.id, &self.miner.labours, &self.miner.unreachable_jobs);
+        if !checks_needs && !has_jobs {
+            return Status::Failure;
+        }
+        // one search around the miner serves the needs and the jobs
+        let search = needs::reachable_from(self.tiles, cell);
+        if checks_needs {
+            self.miner.need_check = NEED_CHECK_DELAY;
+            for &(need, meter) in low_needs.iter() {
+                let distance = match need {
+                    Need::Food => needs::find_food(self.tiles, self.stockpiles, &self.jobs.reservations, self.miner.id,
+                                                   &search)
+                        .and_then(|(food, _)| search.cost(food)),
+                    Need::Water => needs::find_drink_or_water(self.tiles, self.stockpiles, &self.jobs.reservations,
+                                                              self.miner.id, &search)
+                        .and_then(|(water, _)| search.cost(water)),
+                    // a bed is nicer, but anywhere will do for a nap
+                    Need::Rest => Some(0.0),
+                };
+                if distance.is_some() {
+                    let urgency = if need == Need::Food && self.miner.identity.has(Trait::Greedy)
+                        || need == Need::Rest && self.miner.identity.has(Trait::Lazy) {
+                        // a greedy miner gets peckish early, a lazy one tired
+                        1.0 - meter
+                    } else {
+                        (1.0 - meter) * (1.0 - meter)
+                    };
+                    candidates.push(Candidate::new(Choice::Need(need))
+                        .consider("urgency", urgency)
+                        .consider("distance", utility::falloff(distance.unwrap(), 30.0)));
+                }
+            }
+        }
+        // the brave don't mind a longer walk
+        let job_range = if self.miner.identity.has(Trait::Brave) { 45.0 } else { 30.0 };
+        let nearest = self.jobs.nearest_free(self.miner.id, &search, &self.miner.labours, &self.miner.unreachable_jobs,
+                                             JOB_CANDIDATES);
+        for (job_id, cost) in nearest {
+            let job = self.jobs.get(job_id).unwrap();
+            let skill = job.kind.skill().map_or(0.8, |skill| 0.6 + 0.04 * self.miner.skills.level(skill).min(10) as f32);
+            candidates.push(Candidate::new(Choice::Job(job_id))
+                .consider("work", if self.miner.identity.has(Trait::Lazy) { 0.45 } else { 0.6 })
+                .consider("distance", utility::falloff(cost, job_range))
+                .consider("skill", skill)
+                .consider("priority", job.priority as f32 / jobs::MAX_PRIORITY as f32));
+        }
+        match utility::choose(&self.miner.identity.name, &candidates) {
+            Some(Choice::Need(need)) => match self.miner.look_after(need, self.tiles, self.jobs, self.stockpiles) {
+                Some(state) => {
+                    self.miner.state = state;
+                    Status::Success
                 },
-                State::CuttingTree => {
-                    miner.movement_state
+                None => Status::Failure,
+            },
+            Some(Choice::Job(job_id)) => {
+                if self.jobs.claim(job_id, self.miner.id) && self.take_job(job_id) {
+                    Status::Success
+                } else {
+                    Status::Failure
                 }
+            },
+            None => Status::Failure,
+        }
+    }
+
+    fn do_job(&mut self) -> Status {
+        let (miner, tiles, jobs) = (&mut *self.miner, &mut *self.tiles, &mut *self.jobs);
+        let job = miner.job.and_then(|id| jobs.get(id)).map(|job| (job.kind, job.cell, job.tile, job.material));
+        let (kind, cell, tile, material) = match job {
+            Some(job) => job,
+            None => {
+                miner.state = miner.stop_working(jobs, tiles);
+                return Status::Failure;
+            },
+        };
+        let state = miner.state;
+        match state {
+            State::GoingToJob => {
+                if miner.waypoints.len() > 0 {
+                    return Status::Running;
+                }
+                if kind == jobs::JobKind::Haul {
+                    // the item may have been dropped somewhere else since the job was taken
+                    if tiles.cell_at(tiles.tiles[tile].position) != tiles.cell_at(miner.tile.position) {
+                        miner.state = miner.stop_working(jobs, tiles);
+                        return Status::Failure;
+                    }
+                    let picked_up = miner.pick_up(tiles, tile);
+                    if picked_up {
+                        miner.hauling = Some(tile);
+                    }
+                    let haul_to = tiles.cell_position(miner.haul_to.unwrap());
+                    if !picked_up || !miner.route_to(haul_to, tiles, self.hpa) {
+                        // no way from here to the stockpile
+                        let job_id = miner.job.unwrap();
+                        miner.give_up_on(job_id);
+                        miner.state = miner.stop_working(jobs, tiles);
+                        return Status::Failure;
+                    }
+                    miner.state = State::Hauling;
+                } else {
+                    miner.working_on = Some(tile);
+                    let mut factor = kind.skill().map_or(1.0, |skill| miner.skills.duration_factor(skill));
+                    if miner.identity.has(Trait::Lazy) {
+                        factor *= 1.25;
+                    }
+                    miner.work_left = kind.duration() * factor;
+                    miner.state = match kind {
+                        jobs::JobKind::Chop => State::CuttingTree,
+                        jobs::JobKind::Mine | jobs::JobKind::Channel => State::Mining,
+                        jobs::JobKind::Build | jobs::JobKind::Smooth | jobs::JobKind::Fill | jobs::JobKind::Furnish =>
+                            State::Building,
+                        _ => State::Gathering,
+                    };
+                }
+                Status::Running
+            },
+            State::Hauling => {
+                if miner.hauling.is_none() {
+                    miner.state = miner.stop_working(jobs, tiles);
+                    return Status::Failure;
+                }
+                if miner.waypoints.len() > 0 {
+                    return Status::Running;
+                }
+                let (item, haul_to) = (miner.hauling.unwrap(), miner.haul_to.unwrap());
+                miner.drop_at(tiles, item, haul_to);
+                miner.train(kind);
+                jobs.complete(miner.job.unwrap());
+                println!("{} stored an item at {:?}", miner.identity.name, haul_to);
+                miner.unreachable_jobs.clear();
+                miner.state = miner.stop_working(jobs, tiles);
+                Status::Success
+            },
+            State::CuttingTree | State::Mining | State::Gathering | State::Building => {
+                if miner.work_left > 0.0 {
+                    // an exhausted, starving, wounded or unhappy miner works slower too
+                    miner.work_left -= self.duration * miner.needs.speed_factor() * miner.health.speed_factor()
+                        * miner.mood.work_factor();
+                    return Status::Running;
+                }
+                let product = match kind {
+                    jobs::JobKind::Chop => {
+                        tiles.replace(miner.working_on, ::SPRITE_WOOD, true);
+                        miner.working_on
+                    },
+                    jobs::JobKind::Mine => tiles.dig(cell),
+                    jobs::JobKind::Build => {
+                        tiles.build_pillar(cell);
+                        None
+                    },
+                    jobs::JobKind::Channel => {
+                        tiles.channel(cell);
+                        None
+                    },
+                    jobs::JobKind::Smooth => {
+                        tiles.smooth(cell);
+                        None
+                    },
+                    jobs::JobKind::Fill => {
+                        tiles.fill(cell, material.unwrap_or(::SPRITE_FLOOR));
+                        None
+                    },
+                    jobs::JobKind::Furnish => {
+                        tiles.build_bed(cell);
+                        None
+                    },
+                    _ => tiles.gather(miner.working_on.unwrap()),
+                };
+                // routes planned later in the frame already go through the new ground
+                self.hpa.handle_events(tiles, &tiles.events);
+                // skilled hands get more out of the work
+                let bonus = kind.skill().map_or(0, |skill| miner.skills.yield_bonus(skill));
+                if product.is_some() && bonus > 0 {
+                    let count = &mut tiles.tiles[product.unwrap()].resource_count;
+                    *count = count.saturating_add(bonus);
+                }
+                miner.train(kind);
+                if miner.identity.has(Trait::Lazy) {
+                    miner.mood.add(ThoughtKind::Overworked, &miner.identity);
+                }
+                jobs.complete(miner.job.unwrap());
+                println!("{} finished job {}", miner.identity.name, miner.job.unwrap());
+                miner.unreachable_jobs.clear();
+                miner.state = miner.stop_working(jobs, tiles);
+                Status::Success
+            },
+            _ => Status::Failure,
+        }
+    }
+
+    fn tend_need(&mut self) -> Status {
+        let (miner, tiles, jobs, duration) = (&mut *self.miner, &mut *self.tiles, &mut *self.jobs, self.duration);
+        let state = miner.state;
+        let from_cask = miner.needs.draught > 0.0;
+        let done = match state {
+            State::GoingToEat => {
+                let food = miner.working_on.unwrap();
+                if tiles.tiles[food].is_removed || tiles.tiles[food].tex_id != ::SPRITE_FOOD {
+                    miner.state = miner.stop_working(jobs, tiles);
+                    return Status::Failure;
+                }
+                if miner.waypoints.len() == 0 {
+                    let portions = ((1.0 - miner.needs.food) / needs::PORTION).ceil().max(1.0) as u8;
+                    miner.needs.meal = tiles.consume(food, portions) as f32 * needs::PORTION;
+                    miner.working_on = None;
+                    jobs.reservations.release(Target::Tile(food));
+                    miner.state = State::Eating;
+                }
+                false
+            },
+            State::Eating => {
+                let bite = miner.needs.meal.min(needs::EAT_RATE * duration);
+                miner.needs.satisfy(Need::Food, bite);
+                miner.needs.meal -= bite;
+                miner.needs.meal <= 0.0
+            },
+            State::GoingToDrink => {
+                let cask = miner.working_on;
+                if cask.map_or(false, |cask| tiles.tiles[cask].is_removed) {
+                    miner.state = miner.stop_working(jobs, tiles);
+                    return Status::Failure;
+                }
+                if miner.waypoints.len() == 0 {
+                    if cask.is_some() {
+                        // someone may have carried it off in the meantime
+                        let here = tiles.cell_at(miner.tile.position);
+                        if here.map_or(true, |cell| !tiles.items_at(cell).contains(&cask.unwrap())) {
+                            miner.state = miner.stop_working(jobs, tiles);
+                            return Status::Failure;
+                        }
+                        let portions = ((1.0 - miner.needs.water) / needs::PORTION).ceil().max(1.0) as u8;
+                        miner.needs.draught = tiles.consume(cask.unwrap(), portions) as f32 * needs::PORTION;
+                        miner.working_on = None;
+                        jobs.reservations.release(Target::Tile(cask.unwrap()));
+                    }
+                    miner.state = State::Drinking;
+                }
+                false
+            },
+            State::Drinking => {
+                if miner.needs.draught > 0.0 {
+                    let sip = miner.needs.draught.min(needs::DRINK_RATE * duration);
+                    miner.needs.satisfy(Need::Water, sip);
+                    miner.needs.draught -= sip;
+                    miner.needs.draught <= 0.0
+                } else {
+                    miner.needs.satisfy(Need::Water, needs::DRINK_RATE * duration);
+                    miner.needs.water >= 1.0
+                }
+            },
+            State::GoingToBed => {
+                let bed = miner.working_on.unwrap();
+                if tiles.tiles[bed].is_removed {
+                    miner.state = miner.stop_working(jobs, tiles);
+                    return Status::Failure;
+                }
+                if miner.waypoints.len() == 0 {
+                    // the bed stays claimed while it's slept in
+                    miner.state = State::Sleeping;
+                }
+                false
+            },
+            State::Sleeping => {
+                let rate = if miner.working_on.is_some() { needs::BED_SLEEP_RATE } else { needs::SLEEP_RATE };
+                miner.needs.satisfy(Need::Rest, rate * duration);
+                miner.needs.rest >= 1.0
+            },
+            _ => return Status::Failure,
+        };
+        if done {
+            let thought = match state {
+                State::Eating => ThoughtKind::AteRawFood,
+                State::Drinking if from_cask => ThoughtKind::HadADrink,
+                State::Drinking => ThoughtKind::DrankWater,
+                State::Sleeping if miner.working_on.is_some() => ThoughtKind::SleptInABed,
+                _ => ThoughtKind::SleptOnTheFloor,
             };
-            miner.tile.position = match miner.movement_state {
-                MovementState::Idle => miner.tile.position,
-                MovementState::Moving =>
-                    calculate_point(
-                        miner.tile.position, miner.waypoints[miner.waypoints.len() - 1],
-                        miner.speed * duration)
-            };
+            miner.mood.add(thought, &miner.identity);
+            miner.state = miner.stop_working(jobs, tiles);
+            Status::Success
+        } else {
+            Status::Running
+        }
+    }
+
+    /// Takes one step at a time, read from the field shared by everyone going there.
+    fn rally(&mut self) -> Status {
+        let (miner, tiles, flow_fields) = (&mut *self.miner, &*self.tiles, &mut *self.flow_fields);
+        if miner.waypoints.len() > 0 {
+            return Status::Running;
+        }
+        let target = miner.flow_target.unwrap();
+        let next_step = tiles.cell_at(miner.tile.position)
+            .and_then(|cell| flow_fields.next_step(tiles, cell, target));
+        match next_step {
+            Some(next_step) => {
+                miner.waypoints.push(tiles.cell_position(next_step));
+                Status::Running
+            },
+            None => {
+                miner.flow_target = None;
+                Status::Success
+            },
+        }
+    }
+
+    /// Now and then strolls over to some walkable cell nearby.
+    fn wander(&mut self) -> Status {
+        let (miner, tiles) = (&mut *self.miner, &*self.tiles);
+        if miner.waypoints.len() > 0 {
+            return Status::Running;
+        }
+        if rand::random::<f32>() >= 0.2 {
+            return Status::Success;
+        }
+        let target = tiles.get_closest_walkable(miner.tile.position).map(|tile| tile.position);
+        if target.is_some() && miner.route_to(target.unwrap(), tiles, self.hpa) && miner.waypoints.len() > 0 {
+            println!("New route: from {:?} to {:?}, {} steps",
+                     miner.tile.position, miner.waypoints[0], miner.waypoints.len());
+            Status::Running
+        } else {
+            Status::Success
         }
     }
 }
 
-fn calculate_point(a: Vector2<f32>, b: Vector2<f32>, distance: f32) -> Vector2<f32> {
-    a - (a - b).normalize() * distance
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use jobs::{Jobs, JobKind, DEFAULT_PRIORITY};
+    use tiles::Tiles;
+
+    #[test]
+    fn labours() {
+        let mut tiles = Tiles::from_rows(&[
+            "...#",
+            "....",
+            "....",
+            "....",
+        ]);
+        for tile in tiles.tiles.iter_mut() {
+            tile.is_discovered = true;
+        }
+        let mut miners = Miners::new(1, &tiles).unwrap();
+        let mut jobs = Jobs::new();
+        let id = miners.miners[0].id;
+        assert_eq!(miners.get(id).unwrap().labours, jobs::JOB_KINDS.to_vec());
+        assert_eq!(miners.toggle_labour(id, JobKind::Mine, &mut jobs, &mut tiles), Some(false));
+        assert!(!miners.get(id).unwrap().does(JobKind::Mine));
+        assert_eq!(miners.toggle_labour(id, JobKind::Mine, &mut jobs, &mut tiles), Some(true));
+        assert_eq!(miners.toggle_labour(id + 1, JobKind::Mine, &mut jobs, &mut tiles), None);
+        let miner = miners.get_mut(id).unwrap();
+        miner.set_labour(JobKind::Mine, true, &mut jobs, &mut tiles);
+        assert_eq!(miner.labours.iter().filter(|&&kind| kind == JobKind::Mine).count(), 1);
+        // the job at hand is dropped with its labour, and left to someone else
+        jobs.designate(&tiles, &[(3, 0)], JobKind::Mine, DEFAULT_PRIORITY);
+        let job_id = jobs.queue[0].id;
+        assert!(jobs.claim(job_id, id));
+        miner.job = Some(job_id);
+        miner.state = State::GoingToJob;
+        miner.set_labour(JobKind::Chop, false, &mut jobs, &mut tiles);
+        assert_eq!(miner.job, Some(job_id));
+        miner.set_labour(JobKind::Mine, false, &mut jobs, &mut tiles);
+        assert_eq!(miner.job, None);
+        assert!(miner.state == State::Idle);
+        assert!(jobs.claim(job_id, id + 1));
+    }
+
+    #[test]
+    fn rain_falls_outside() {
+        let mut tiles = Tiles::from_rows(&[
+            "...#",
+            "....",
+            "....",
+            "....",
+        ]);
+        tiles.dig((3, 0));
+        let mut miners = Miners::new(2, &tiles).unwrap();
+        miners.miners[0].tile.position = tiles.cell_position((0, 0));
+        miners.miners[1].tile.position = tiles.cell_position((3, 0));
+        miners.rain_on(&tiles);
+        miners.rain_on(&tiles);
+        let rained_on = |miner: &Miner| miner.mood.thoughts.iter().filter(|t| t.kind == ThoughtKind::RainedOn).count();
+        assert_eq!(rained_on(&miners.miners[0]), 1);
+        assert_eq!(rained_on(&miners.miners[1]), 0);
+    }
 }
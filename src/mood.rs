@@ -0,0 +1,261 @@
+use rand;
+use identity::{Identity, Trait};
+
+/// Thoughts of one kind that count towards the mood at once, more only keep them fresh
+const STACK_LIMIT: usize = 3;
+/// Below this mood a miner may break down
+const BREAKING_POINT: f32 = -40.0;
+/// Chance per second of breaking down at the worst mood, less the closer to the breaking point
+const BREAKDOWN_RATE: f32 = 0.02;
+/// Seconds a breakdown lasts
+const BREAKDOWN_DURATION: f32 = 30.0;
+/// How many thoughts are kept around to be read
+const MEMORY: usize = 20;
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum ThoughtKind {
+    AteRawFood,
+    DrankWater,
+    /// from a cask kept in the stores
+    HadADrink,
+    /// no bed was free
+    SleptOnTheFloor,
+    SleptInABed,
+    /// out under the open sky in a shower
+    RainedOn,
+    WentHungry,
+    WentThirsty,
+    CaughtInCaveIn,
+    SawDeath,
+    FriendHurt,
+    FriendDied,
+    SpouseDied,
+    /// refreshed while near a friend or spouse
+    WithFriend,
+    /// refreshed while near a rival
+    WithRival,
+    GotMarried,
+    LearnedSomething,
+    /// a lazy miner after a job
+    Overworked,
+}
+
+impl ThoughtKind {
+    /// How much the thought lifts or sinks the mood and for how many seconds, for someone with these traits.
+    fn effect(&self, identity: &Identity) -> (f32, f32) {
+        match *self {
+            ThoughtKind::AteRawFood => if identity.has(Trait::Greedy) { (-5.0, 300.0) } else { (3.0, 300.0) },
+            ThoughtKind::DrankWater => (2.0, 120.0),
+            ThoughtKind::HadADrink => (4.0, 300.0),
+            ThoughtKind::SleptOnTheFloor => (-5.0, 600.0),
+            ThoughtKind::SleptInABed => (3.0, 600.0),
+            ThoughtKind::RainedOn => (-3.0, 300.0),
+            ThoughtKind::WentHungry => (-10.0, 300.0),
+            ThoughtKind::WentThirsty => (-10.0, 300.0),
+            ThoughtKind::CaughtInCaveIn => if identity.has(Trait::Brave) { (-5.0, 600.0) } else { (-15.0, 900.0) },
+            ThoughtKind::SawDeath => if identity.has(Trait::Brave) { (-10.0, 900.0) } else { (-20.0, 1800.0) },
+            ThoughtKind::FriendHurt => (-5.0, 300.0),
+            ThoughtKind::FriendDied => if identity.has(Trait::Brave) { (-20.0, 1800.0) } else { (-30.0, 3600.0) },
+            ThoughtKind::SpouseDied => (-50.0, 7200.0),
+            ThoughtKind::WithFriend => (5.0, 120.0),
+            ThoughtKind::WithRival => (-5.0, 120.0),
+            ThoughtKind::GotMarried => (20.0, 3600.0),
+            ThoughtKind::LearnedSomething => (5.0, 600.0),
+            ThoughtKind::Overworked => (-2.0, 300.0),
+        }
+    }
+
+    pub fn describe(&self) -> &'static str {
+        match *self {
+            ThoughtKind::AteRawFood => "ate raw food",
+            ThoughtKind::DrankWater => "drank some fresh water",
+            ThoughtKind::HadADrink => "had a drink from the stores",
+            ThoughtKind::SleptOnTheFloor => "slept on the hard floor",
+            ThoughtKind::SleptInABed => "slept in a bed",
+            ThoughtKind::RainedOn => "got rained on",
+            ThoughtKind::WentHungry => "went hungry",
+            ThoughtKind::WentThirsty => "went thirsty",
+            ThoughtKind::CaughtInCaveIn => "was caught in a cave-in",
+            ThoughtKind::SawDeath => "saw someone die",
+            ThoughtKind::FriendHurt => "saw a friend get hurt",
+            ThoughtKind::FriendDied => "lost a friend",
+            ThoughtKind::SpouseDied => "lost their partner",
+            ThoughtKind::WithFriend => "spent time with a friend",
+            ThoughtKind::WithRival => "had to put up with a rival",
+            ThoughtKind::GotMarried => "got married",
+            ThoughtKind::LearnedSomething => "got better at a craft",
+            ThoughtKind::Overworked => "had to work",
+        }
+    }
+}
+
+/// Something a miner remembers and how it still weighs on its mood.
+#[derive(Clone, Debug)]
+pub struct Thought {
+    pub kind: ThoughtKind,
+    pub effect: f32,
+    /// Seconds until the thought stops counting
+    pub time_left: f32,
+}
+
+/// The memories of a miner adding up to how it feels.
+pub struct Mood {
+    /// Latest last
+    pub thoughts: Vec<Thought>,
+    /// Seconds left of a breakdown
+    pub breakdown: f32,
+}
+
+impl Mood {
+    pub fn new() -> Mood {
+        Mood {
+            thoughts: Vec::new(),
+            breakdown: 0.0,
+        }
+    }
+
+    pub fn add(&mut self, kind: ThoughtKind, identity: &Identity) {
+        let (effect, duration) = kind.effect(identity);
+        self.thoughts.push(Thought {
+            kind: kind,
+            effect: effect,
+            time_left: duration,
+        });
+        if self.thoughts.len() > MEMORY {
+            self.thoughts.remove(0);
+        }
+    }
+
+    /// Whether the miner had a thought of this kind lately.
+    pub fn remembers(&self, kind: ThoughtKind) -> bool {
+        self.thoughts.iter().any(|thought| thought.kind == kind && thought.time_left > 0.0)
+    }
+
+    /// Sum of the thoughts that still count, from -100 to 100.
+    pub fn score(&self) -> f32 {
+        let mut counted = Vec::new();
+        let mut score = 0.0;
+        // the freshest thoughts of a kind count
+        for thought in self.thoughts.iter().rev().filter(|thought| thought.time_left > 0.0) {
+            if counted.iter().filter(|&&kind| kind == thought.kind).count() < STACK_LIMIT {
+                counted.push(thought.kind);
+                score += thought.effect;
+            }
+        }
+        score.max(-100.0).min(100.0)
+    }
+
+    /// How much of the usual pace a miner in this mood works at, from 0.75 to 1.25.
+    pub fn work_factor(&self) -> f32 {
+        1.0 + self.score() / 400.0
+    }
+
+    pub fn is_broken_down(&self) -> bool {
+        self.breakdown > 0.0
+    }
+
+    /// Lets thoughts fade and a breakdown pass, or starts one.
+    ///
+    /// Returns true when the miner just broke down.
+    pub fn update(&mut self, duration: f32) -> bool {
+        for thought in self.thoughts.iter_mut() {
+            thought.time_left = (thought.time_left - duration).max(0.0);
+        }
+        if self.breakdown > 0.0 {
+            self.breakdown -= duration;
+            return false;
+        }
+        let score = self.score();
+        if score >= BREAKING_POINT {
+            return false;
+        }
+        let chance = BREAKDOWN_RATE * (BREAKING_POINT - score) / (100.0 + BREAKING_POINT) * duration;
+        if rand::random::<f32>() < chance {
+            self.breakdown = BREAKDOWN_DURATION;
+            return true;
+        }
+        false
+    }
+
+    /// The latest thoughts, most recent first.
+    pub fn recent(&self, count: usize) -> Vec<&Thought> {
+        self.thoughts.iter().rev().take(count).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn plain() -> Identity {
+        let mut identity = Identity::generate();
+        identity.traits.clear();
+        identity
+    }
+
+    #[test]
+    fn stacking() {
+        let identity = plain();
+        let mut mood = Mood::new();
+        for _ in 0..5 {
+            mood.add(ThoughtKind::WentHungry, &identity);
+        }
+        assert_eq!(mood.score(), -30.0);
+        mood.add(ThoughtKind::DrankWater, &identity);
+        assert_eq!(mood.score(), -28.0);
+        for _ in 0..3 {
+            mood.add(ThoughtKind::SawDeath, &identity);
+            mood.add(ThoughtKind::CaughtInCaveIn, &identity);
+        }
+        assert_eq!(mood.score(), -100.0);
+        assert_eq!(mood.work_factor(), 0.75);
+    }
+
+    #[test]
+    fn thoughts_fade() {
+        let identity = plain();
+        let mut mood = Mood::new();
+        mood.add(ThoughtKind::DrankWater, &identity);
+        mood.add(ThoughtKind::WentHungry, &identity);
+        mood.update(200.0);
+        assert!(!mood.remembers(ThoughtKind::DrankWater));
+        assert!(mood.remembers(ThoughtKind::WentHungry));
+        assert_eq!(mood.score(), -10.0);
+        mood.update(100.0);
+        assert_eq!(mood.score(), 0.0);
+        for _ in 0..MEMORY + 5 {
+            mood.add(ThoughtKind::DrankWater, &identity);
+        }
+        assert_eq!(mood.thoughts.len(), MEMORY);
+        assert_eq!(mood.recent(2).len(), 2);
+    }
+
+    #[test]
+    fn traits_matter() {
+        let mut brave = plain();
+        brave.traits.push(Trait::Brave);
+        let mut mood = Mood::new();
+        mood.add(ThoughtKind::CaughtInCaveIn, &brave);
+        assert_eq!(mood.score(), -5.0);
+        mood.add(ThoughtKind::CaughtInCaveIn, &plain());
+        assert_eq!(mood.score(), -20.0);
+    }
+
+    #[test]
+    fn breakdown() {
+        let identity = plain();
+        let mut mood = Mood::new();
+        for _ in 0..3 {
+            mood.add(ThoughtKind::SawDeath, &identity);
+            mood.add(ThoughtKind::CaughtInCaveIn, &identity);
+        }
+        while !mood.update(1.0) {}
+        assert!(mood.is_broken_down());
+        mood.update(BREAKDOWN_DURATION + 1.0);
+        assert!(!mood.is_broken_down());
+        // a happy miner doesn't break down
+        let mut mood = Mood::new();
+        mood.add(ThoughtKind::LearnedSomething, &identity);
+        assert!((0..1000).all(|_| !mood.update(1.0)));
+    }
+}
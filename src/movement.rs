@@ -0,0 +1,76 @@
+use cgmath::Vector2;
+use cgmath::prelude::*;
+use hpa;
+use tiles;
+
+#[derive(Copy, Clone, PartialEq)]
+pub enum MovementState {
+    Moving,
+    Idle,
+}
+
+/// Something that walks the map from cell to cell: miners and animals.
+pub trait Walker {
+    fn position(&self) -> Vector2<f32>;
+    fn set_position(&mut self, position: Vector2<f32>);
+    /// Where it still has to walk through, consumed from the back
+    fn waypoints(&mut self) -> &mut Vec<Vector2<f32>>;
+
+    /// Plans a route to the cell under `target` and fills the waypoints with it.
+    ///
+    /// Leaves the waypoints untouched and returns false if there is no route.
+    fn route_to(&mut self, target: Vector2<f32>, tiles: &tiles::Tiles, hpa: &hpa::Hpa) -> bool {
+        let (from, to) = match (tiles.cell_at(self.position()), tiles.cell_at(target)) {
+            (Some(from), Some(to)) => (from, to),
+            _ => return false,
+        };
+        match hpa.find_path(tiles, from, to) {
+            Some(path) => {
+                self.follow_path(&path, tiles);
+                true
+            },
+            None => false,
+        }
+    }
+
+    /// Fills the waypoints with a route of cells.
+    fn follow_path(&mut self, path: &[(usize, usize)], tiles: &tiles::Tiles) {
+        *self.waypoints() = path.iter().rev().map(|&cell| tiles.cell_position(cell)).collect();
+    }
+
+    /// Drops the next waypoint once it's reached.
+    fn follow_waypoints(&mut self) -> MovementState {
+        let position = self.position();
+        let waypoints = self.waypoints();
+        if waypoints.len() < 1 {
+            MovementState::Idle
+        } else if (position - waypoints[waypoints.len() - 1]).magnitude() < 2.0 {
+            waypoints.pop();
+            if waypoints.len() > 0 {
+                MovementState::Moving
+            } else {
+                MovementState::Idle
+            }
+        } else {
+            MovementState::Moving
+        }
+    }
+
+    /// Goes up to `distance` further along the waypoints.
+    fn walk(&mut self, distance: f32) -> MovementState {
+        let state = self.follow_waypoints();
+        if state == MovementState::Moving {
+            let (position, next) = (self.position(), *self.waypoints().last().unwrap());
+            self.set_position(calculate_point(position, next, distance));
+        }
+        state
+    }
+}
+
+fn calculate_point(a: Vector2<f32>, b: Vector2<f32>, distance: f32) -> Vector2<f32> {
+    if (a - b).magnitude() <= distance {
+        // don't overshoot the waypoint
+        return b;
+    }
+    a - (a - b).normalize() * distance
+}
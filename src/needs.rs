@@ -0,0 +1,314 @@
+use pathfinding;
+use pathfinding::Search;
+use reservations::{Reservations, Target};
+use stockpiles::Stockpiles;
+use tiles::Tiles;
+
+/// Fraction of a full meter lost per second
+const HUNGER_RATE: f32 = 1.0 / 600.0;
+const THIRST_RATE: f32 = 1.0 / 400.0;
+const FATIGUE_RATE: f32 = 1.0 / 900.0;
+/// Below this a miner drops its job to look after itself
+const URGENT: f32 = 0.25;
+/// Below this a lazy miner already wants a rest
+const LAZY_URGENT_REST: f32 = 0.4;
+/// Health lost per second for every empty meter
+const DEPRIVATION_DAMAGE: f32 = 0.5;
+/// Meter filled by one portion of food or drink
+pub const PORTION: f32 = 0.35;
+/// Meter filled per second while eating, drinking or sleeping on the floor or in a bed
+pub const EAT_RATE: f32 = 0.2;
+pub const DRINK_RATE: f32 = 0.25;
+pub const SLEEP_RATE: f32 = 1.0 / 120.0;
+pub const BED_SLEEP_RATE: f32 = 1.0 / 60.0;
+/// How much longer a walk to the stores may be than to what lies around, in cells
+const STORES_DETOUR: f32 = 10.0;
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Need {
+    Food,
+    Water,
+    Rest,
+}
+
+/// How fed, watered and rested a miner is, each from 0 (deprived) to 1 (satisfied).
+pub struct Needs {
+    pub food: f32,
+    pub water: f32,
+    pub rest: f32,
+    /// Food already taken that is still being eaten
+    pub meal: f32,
+    /// Drink already taken from the stores that is still being drunk
+    pub draught: f32,
+}
+
+impl Needs {
+    pub fn new() -> Needs {
+        Needs {
+            food: 1.0,
+            water: 1.0,
+            rest: 1.0,
+            meal: 0.0,
+            draught: 0.0,
+        }
+    }
+
+    pub fn get(&self, need: Need) -> f32 {
+        match need {
+            Need::Food => self.food,
+            Need::Water => self.water,
+            Need::Rest => self.rest,
+        }
+    }
+
+    /// Lets the meters fall over some time, apart from the one being looked after.
+    ///
+    /// Returns the health lost to empty meters.
+    pub fn update(&mut self, duration: f32, tending: Option<Need>) -> f32 {
+        if tending != Some(Need::Food) {
+            self.food = (self.food - HUNGER_RATE * duration).max(0.0);
+        }
+        if tending != Some(Need::Water) {
+            self.water = (self.water - THIRST_RATE * duration).max(0.0);
+        }
+        if tending != Some(Need::Rest) {
+            self.rest = (self.rest - FATIGUE_RATE * duration).max(0.0);
+        }
+        let empty = [self.food, self.water, self.rest].iter().filter(|&&meter| meter <= 0.0).count();
+        empty as f32 * DEPRIVATION_DAMAGE * duration
+    }
+
+    /// Fills a meter up by some amount.
+    pub fn satisfy(&mut self, need: Need, amount: f32) {
+        match need {
+            Need::Food => self.food = (self.food + amount).min(1.0),
+            Need::Water => self.water = (self.water + amount).min(1.0),
+            Need::Rest => self.rest = (self.rest + amount).min(1.0),
+        }
+    }
+
+    /// The lowest meter below the urgent mark, which comes earlier for rest when the miner is lazy.
+    pub fn most_urgent(&self, lazy: bool) -> Option<Need> {
+        let mut urgent = [Need::Water, Need::Food, Need::Rest].iter()
+            .map(|&need| (need, self.get(need)))
+            .filter(|&(need, meter)| meter < if lazy && need == Need::Rest { LAZY_URGENT_REST } else { URGENT })
+            .collect::<Vec<_>>();
+        urgent.sort_by(|a, b| a.1.partial_cmp(&b.1).unwrap());
+        urgent.first().map(|&(need, _)| need)
+    }
+
+    /// How much of the usual speed is left, every urgent need slowing a miner down.
+    pub fn speed_factor(&self) -> f32 {
+        [self.food, self.water, self.rest].iter().fold(1.0, |factor, &meter| {
+            if meter < URGENT { factor * 0.75 } else { factor }
+        })
+    }
+}
+
+/// Closest food that nobody else claimed, and the cell it lies on. Stored food is eaten first.
+pub fn find_food(tiles: &Tiles, stockpiles: &Stockpiles, reservations: &Reservations, miner_id: usize,
+                 search: &Search) -> Option<((usize, usize), usize)> {
+    find_item(tiles, Some(stockpiles), reservations, miner_id, search, ::SPRITE_FOOD)
+}
+
+/// Closest cask of drink that nobody else claimed, and the cell it lies on. Stored drink is drunk first.
+pub fn find_drink(tiles: &Tiles, stockpiles: &Stockpiles, reservations: &Reservations, miner_id: usize,
+                  search: &Search) -> Option<((usize, usize), usize)> {
+    find_item(tiles, Some(stockpiles), reservations, miner_id, search, ::SPRITE_DRINK)
+}
+
+/// Where to go for a drink: the closest cask and the cell it lies on, unless water is a lot closer.
+pub fn find_drink_or_water(tiles: &Tiles, stockpiles: &Stockpiles, reservations: &Reservations, miner_id: usize,
+                           search: &Search) -> Option<((usize, usize), Option<usize>)> {
+    let cask = find_drink(tiles, stockpiles, reservations, miner_id, search);
+    let water = find_water(tiles, search);
+    match (cask, water) {
+        (Some((cell, _)), Some(water)) if search.cost(cell).unwrap() > search.cost(water).unwrap() + STORES_DETOUR =>
+            Some((water, None)),
+        (Some((cell, cask)), _) => Some((cell, Some(cask))),
+        (None, water) => water.map(|cell| (cell, None)),
+    }
+}
+
+/// Closest bed nobody else sleeps in, and its cell.
+pub fn find_bed(tiles: &Tiles, reservations: &Reservations, miner_id: usize, search: &Search)
+    -> Option<((usize, usize), usize)>
+{
+    find_item(tiles, None, reservations, miner_id, search, ::SPRITE_BED)
+}
+
+/// Closest item of a kind lying on the ground that nobody else claimed, and the cell it lies on.
+///
+/// Given the stockpiles, the items kept there come before anything lying around elsewhere that isn't a lot closer.
+fn find_item(tiles: &Tiles, stockpiles: Option<&Stockpiles>, reservations: &Reservations, miner_id: usize,
+             search: &Search, tex_id: u32) -> Option<((usize, usize), usize)> {
+    // the closest stored item and the closest one lying around
+    let mut best: [Option<(f32, (usize, usize), usize)>; 2] = [None, None];
+    for (id, tile) in tiles.tiles.iter().enumerate() {
+        if tile.is_removed || tile.tex_id != tex_id || (tile.resource_id.is_some() && tile.resource_count == 0)
+            || reservations.is_taken(Target::Tile(id), miner_id) {
+            continue;
+        }
+        let cell = match tiles.cell_at(tile.position) {
+            Some(cell) => cell,
+            None => continue,
+        };
+        // carried or unseen items are out of reach
+        if !tiles.resources_at(cell, true).contains(&id) {
+            continue;
+        }
+        let cost = match search.cost(cell) {
+            Some(cost) => cost,
+            None => continue,
+        };
+        let stored = stockpiles.map_or(false, |stockpiles| stockpiles.is_stored(tiles, id));
+        let best = &mut best[if stored { 0 } else { 1 }];
+        if best.map_or(true, |(c, _, _)| cost < c) {
+            *best = Some((cost, cell, id));
+        }
+    }
+    let best = match (best[0], best[1]) {
+        (Some(stored), Some(lying)) if stored.0 > lying.0 + STORES_DETOUR => Some(lying),
+        (Some(stored), _) => Some(stored),
+        (None, lying) => lying,
+    };
+    best.map(|(_, cell, id)| (cell, id))
+}
+
+/// Closest reachable cell next to water.
+pub fn find_water(tiles: &Tiles, search: &Search) -> Option<(usize, usize)> {
+    let mut best: Option<(f32, (usize, usize))> = None;
+    for x in 0..tiles.width {
+        for y in 0..tiles.height {
+            let cost = search.cost((x, y));
+            if cost.is_none() || best.map_or(false, |(c, _)| cost.unwrap() >= c) {
+                continue;
+            }
+            if is_by_water(tiles, (x, y)) {
+                best = Some((cost.unwrap(), (x, y)));
+            }
+        }
+    }
+    best.map(|(_, cell)| cell)
+}
+
+fn is_by_water(tiles: &Tiles, cell: (usize, usize)) -> bool {
+    for dx in -1..2 {
+        for dy in -1..2 {
+            let (x, y) = (cell.0 as isize + dx, cell.1 as isize + dy);
+            if x < 0 || y < 0 || x >= tiles.width as isize || y >= tiles.height as isize {
+                continue;
+            }
+            let next = (x as usize, y as usize);
+            if tiles.ground_at(next).tex_id == ::SPRITE_WATER && tiles.is_discovered(next) {
+                return true;
+            }
+        }
+    }
+    false
+}
+
+/// Everything a miner can walk to from a cell.
+pub fn reachable_from(tiles: &Tiles, cell: (usize, usize)) -> Search {
+    pathfinding::search(tiles, cell, None, pathfinding::map_bounds(tiles))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tiles::Tile;
+
+    /// A discovered field with a pond in the top right corner.
+    fn field() -> Tiles {
+        let mut tiles = Tiles::from_rows(&[
+            "...~",
+            "....",
+            "....",
+            "....",
+        ]);
+        for tile in tiles.tiles.iter_mut() {
+            tile.is_discovered = true;
+        }
+        tiles
+    }
+
+    fn drop_item(tiles: &mut Tiles, cell: (usize, usize), tex_id: u32, resource_id: Option<u8>) -> usize {
+        let mut item = Tile::new(tiles.cell_position(cell), tex_id, resource_id);
+        item.can_be_carried = resource_id.is_some();
+        tiles.spawn(item)
+    }
+
+    #[test]
+    fn meters_fall() {
+        let mut needs = Needs::new();
+        assert_eq!(needs.update(60.0, None), 0.0);
+        assert!((needs.food - (1.0 - 60.0 * HUNGER_RATE)).abs() < 1e-6);
+        assert!((needs.water - (1.0 - 60.0 * THIRST_RATE)).abs() < 1e-6);
+        assert!((needs.rest - (1.0 - 60.0 * FATIGUE_RATE)).abs() < 1e-6);
+        // the need looked after doesn't fall any further
+        let rest = needs.rest;
+        needs.update(60.0, Some(Need::Rest));
+        assert_eq!(needs.rest, rest);
+        assert_eq!(needs.most_urgent(false), None);
+        needs.food = 0.2;
+        needs.water = 0.1;
+        assert_eq!(needs.most_urgent(false), Some(Need::Water));
+        assert_eq!(needs.speed_factor(), 0.75 * 0.75);
+        // a lazy miner wants a rest before anyone else would
+        needs = Needs::new();
+        needs.rest = 0.3;
+        assert_eq!(needs.most_urgent(false), None);
+        assert_eq!(needs.most_urgent(true), Some(Need::Rest));
+    }
+
+    #[test]
+    fn empty_meters_hurt() {
+        let mut needs = Needs::new();
+        needs.food = 0.0;
+        assert_eq!(needs.update(2.0, None), 2.0 * DEPRIVATION_DAMAGE);
+        needs.water = 0.001;
+        assert_eq!(needs.update(2.0, None), 4.0 * DEPRIVATION_DAMAGE);
+        assert_eq!(needs.water, 0.0);
+        // a filled meter stops hurting
+        needs.satisfy(Need::Food, 2.0);
+        assert_eq!(needs.food, 1.0);
+        assert_eq!(needs.update(2.0, None), 2.0 * DEPRIVATION_DAMAGE);
+    }
+
+    #[test]
+    fn stores_come_first() {
+        let mut tiles = field();
+        let mut stockpiles = Stockpiles::new();
+        let mut reservations = Reservations::new();
+        stockpiles.designate(&tiles, &[(3, 3)], vec![::RESOURCE_FOOD, ::RESOURCE_DRINK], 5);
+        let search = reachable_from(&tiles, (0, 0));
+        assert_eq!(find_food(&tiles, &stockpiles, &reservations, 7, &search), None);
+        assert_eq!(find_drink_or_water(&tiles, &stockpiles, &reservations, 7, &search), Some(((2, 0), None)));
+        let lying = drop_item(&mut tiles, (1, 0), ::SPRITE_FOOD, Some(::RESOURCE_FOOD));
+        assert_eq!(find_food(&tiles, &stockpiles, &reservations, 7, &search), Some(((1, 0), lying)));
+        let stored = drop_item(&mut tiles, (3, 3), ::SPRITE_FOOD, Some(::RESOURCE_FOOD));
+        assert_eq!(find_food(&tiles, &stockpiles, &reservations, 7, &search), Some(((3, 3), stored)));
+        // what someone else went for is left to them
+        reservations.reserve(Target::Tile(stored), 8);
+        assert_eq!(find_food(&tiles, &stockpiles, &reservations, 7, &search), Some(((1, 0), lying)));
+        // a cask beats the pond, even further away
+        let cask = drop_item(&mut tiles, (3, 3), ::SPRITE_DRINK, Some(::RESOURCE_DRINK));
+        assert_eq!(find_drink_or_water(&tiles, &stockpiles, &reservations, 7, &search), Some(((3, 3), Some(cask))));
+    }
+
+    #[test]
+    fn free_beds() {
+        let mut tiles = field();
+        let mut reservations = Reservations::new();
+        let search = reachable_from(&tiles, (0, 0));
+        assert_eq!(find_bed(&tiles, &reservations, 7, &search), None);
+        let far = tiles.build_bed((3, 2)).unwrap();
+        let near = tiles.build_bed((1, 1)).unwrap();
+        // only on an empty floor
+        assert_eq!(tiles.build_bed((1, 1)), None);
+        assert_eq!(tiles.build_bed((3, 0)), None);
+        assert_eq!(find_bed(&tiles, &reservations, 7, &search), Some(((1, 1), near)));
+        reservations.reserve(Target::Tile(near), 8);
+        assert_eq!(find_bed(&tiles, &reservations, 7, &search), Some(((3, 2), far)));
+    }
+}
@@ -0,0 +1,366 @@
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+use std::f32;
+use tiles;
+use tiles::Tiles;
+
+/// Steps to the eight neighbours of a cell and their length
+const NEIGHBOURS: [(isize, isize, f32); 8] = [
+    (1, 0, 1.0), (-1, 0, 1.0), (0, 1, 1.0), (0, -1, 1.0),
+    (1, 1, f32::consts::SQRT_2), (1, -1, f32::consts::SQRT_2),
+    (-1, 1, f32::consts::SQRT_2), (-1, -1, f32::consts::SQRT_2),
+];
+
+/// Returns the cost of stepping onto ground of this kind, `None` if it can't be walked on.
+pub fn tile_cost(tex_id: u32) -> Option<f32> {
+    if !tiles::is_walkable(tex_id) {
+        return None;
+    }
+    match tex_id {
+        ::SPRITE_CLAY => Some(2.0),
+        _ => Some(1.0),
+    }
+}
+
+/// Cost of walking onto a cell, `None` if it is outside the map or not walkable.
+pub fn cell_cost(tiles: &Tiles, x: isize, y: isize) -> Option<f32> {
+    if x < 0 || y < 0 || x >= tiles.width as isize || y >= tiles.height as isize {
+        return None;
+    }
+    tile_cost(tiles.ground_at((x as usize, y as usize)).tex_id)
+}
+
+/// Octile distance, never more than the real cost since no step costs less than 1
+pub fn heuristic(a: (usize, usize), b: (usize, usize)) -> f32 {
+    let dx = (a.0 as f32 - b.0 as f32).abs();
+    let dy = (a.1 as f32 - b.1 as f32).abs();
+    dx.max(dy) + (f32::consts::SQRT_2 - 1.0) * dx.min(dy)
+}
+
+/// Walkable neighbours of a cell with the cost of moving to them.
+///
+/// Diagonal moves are only allowed when both cells they cut past are walkable.
+pub fn neighbours(tiles: &Tiles, cell: (usize, usize)) -> Vec<((usize, usize), f32)> {
+    let (x, y) = (cell.0 as isize, cell.1 as isize);
+    NEIGHBOURS.iter().filter_map(|&(dx, dy, length)| {
+        let cost = cell_cost(tiles, x + dx, y + dy);
+        if cost.is_none() {
+            return None;
+        }
+        if dx != 0 && dy != 0 && (cell_cost(tiles, x + dx, y).is_none() || cell_cost(tiles, x, y + dy).is_none()) {
+            return None;
+        }
+        Some((((x + dx) as usize, (y + dy) as usize), cost.unwrap() * length))
+    }).collect()
+}
+
+/// Cost of walking a route starting next to `from`.
+pub fn route_cost(tiles: &Tiles, from: (usize, usize), route: &[(usize, usize)]) -> f32 {
+    let mut cost = 0.0;
+    let mut previous = from;
+    for &cell in route.iter() {
+        let length = if previous.0 != cell.0 && previous.1 != cell.1 { f32::consts::SQRT_2 } else { 1.0 };
+        cost += cell_cost(tiles, cell.0 as isize, cell.1 as isize).unwrap_or(f32::INFINITY) * length;
+        previous = cell;
+    }
+    cost
+}
+
+/// Part of the map a search is limited to, from the first corner up to but excluding the second
+pub type Bounds = ((usize, usize), (usize, usize));
+
+pub fn map_bounds(tiles: &Tiles) -> Bounds {
+    ((0, 0), (tiles.width, tiles.height))
+}
+
+pub fn in_bounds(bounds: Bounds, cell: (usize, usize)) -> bool {
+    let ((x0, y0), (x1, y1)) = bounds;
+    cell.0 >= x0 && cell.1 >= y0 && cell.0 < x1 && cell.1 < y1
+}
+
+/// An entry of the open list, ordered so that `BinaryHeap` pops the cheapest one first
+#[derive(Copy, Clone, PartialEq)]
+pub struct Node {
+    pub estimate: f32,
+    pub cell_idx: usize,
+}
+
+impl Eq for Node {}
+
+impl Ord for Node {
+    fn cmp(&self, other: &Node) -> Ordering {
+        other.estimate.partial_cmp(&self.estimate).unwrap_or(Ordering::Equal)
+    }
+}
+
+impl PartialOrd for Node {
+    fn partial_cmp(&self, other: &Node) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Costs of reaching the cells within some bounds from one origin cell.
+pub struct Search {
+    pub bounds: Bounds,
+    pub origin: (usize, usize),
+    pub costs: Vec<f32>,
+    came_from: Vec<Option<usize>>,
+}
+
+impl Search {
+    fn index(&self, cell: (usize, usize)) -> usize {
+        let ((x0, y0), (_, y1)) = self.bounds;
+        (cell.0 - x0) * (y1 - y0) + (cell.1 - y0)
+    }
+
+    fn cell(&self, idx: usize) -> (usize, usize) {
+        let ((x0, y0), (_, y1)) = self.bounds;
+        (x0 + idx / (y1 - y0), y0 + idx % (y1 - y0))
+    }
+
+    /// Cost of the cheapest route found to a cell, `None` if it wasn't reached.
+    pub fn cost(&self, cell: (usize, usize)) -> Option<f32> {
+        if !in_bounds(self.bounds, cell) || self.costs[self.index(cell)] == f32::INFINITY {
+            return None;
+        }
+        Some(self.costs[self.index(cell)])
+    }
+
+    /// The cell a cell was reached from, `None` for the origin and unreached cells.
+    pub fn previous(&self, cell: (usize, usize)) -> Option<(usize, usize)> {
+        if !in_bounds(self.bounds, cell) {
+            return None;
+        }
+        self.came_from[self.index(cell)].map(|idx| self.cell(idx))
+    }
+
+    /// Route from the origin to a cell, excluding the origin and ending with the cell.
+    pub fn path_to(&self, cell: (usize, usize)) -> Option<Vec<(usize, usize)>> {
+        if self.cost(cell).is_none() {
+            return None;
+        }
+        let mut path = Vec::new();
+        let (start, mut current) = (self.index(self.origin), self.index(cell));
+        while current != start {
+            path.push(self.cell(current));
+            current = self.came_from[current].unwrap();
+        }
+        path.reverse();
+        Some(path)
+    }
+}
+
+/// Explores the cells within the bounds from `from`.
+///
+/// With a goal this is A* and stops as soon as the goal is reached,
+/// without one it is Dijkstra over everything reachable within the bounds.
+pub fn search(tiles: &Tiles, from: (usize, usize), goal: Option<(usize, usize)>, bounds: Bounds) -> Search {
+    explore(tiles, from, goal, &[], bounds, false)
+}
+
+/// Same as `search` without a goal, stopping once the cheapest routes to all the targets are known.
+pub fn search_to_all(tiles: &Tiles, from: (usize, usize), targets: &[(usize, usize)], bounds: Bounds) -> Search {
+    explore(tiles, from, None, targets, bounds, false)
+}
+
+/// Explores the cells within the bounds that can reach `to`, with the costs of walking from them to it.
+///
+/// Entering a cell costs what its ground costs, so walking a route back doesn't cost
+/// the same as walking it out: each step is charged the cell it leads back into.
+pub fn search_back(tiles: &Tiles, to: (usize, usize), bounds: Bounds) -> Search {
+    explore(tiles, to, None, &[], bounds, true)
+}
+
+/// Same as `search_back`, stopping once the cheapest routes from all the targets are known.
+pub fn search_back_from_all(tiles: &Tiles, to: (usize, usize), targets: &[(usize, usize)], bounds: Bounds) -> Search {
+    explore(tiles, to, None, targets, bounds, true)
+}
+
+/// Explores from `from` until the goal is reached, every target is settled or nothing is left within the bounds.
+fn explore(tiles: &Tiles, from: (usize, usize), goal: Option<(usize, usize)>, targets: &[(usize, usize)],
+           bounds: Bounds, backwards: bool) -> Search
+{
+    let ((x0, y0), (x1, y1)) = bounds;
+    let mut search = Search {
+        bounds: bounds,
+        origin: from,
+        costs: vec![f32::INFINITY; (x1 - x0) * (y1 - y0)],
+        came_from: vec![None; (x1 - x0) * (y1 - y0)],
+    };
+    let estimate = |cell: (usize, usize)| goal.map_or(0.0, |to| heuristic(cell, to));
+    let mut open = BinaryHeap::new();
+    let start = search.index(from);
+    search.costs[start] = 0.0;
+    open.push(Node { estimate: estimate(from), cell_idx: start });
+    let mut unsettled = targets.len();
+
+    while let Some(Node { estimate: node_estimate, cell_idx }) = open.pop() {
+        let cell = search.cell(cell_idx);
+        if goal == Some(cell) {
+            break;
+        }
+        if node_estimate > search.costs[cell_idx] + estimate(cell) {
+            // stale entry, the cell was reached cheaper since
+            continue;
+        }
+        if targets.contains(&cell) {
+            unsettled -= 1;
+            if unsettled == 0 {
+                break;
+            }
+        }
+        for (next, step_cost) in neighbours(tiles, cell) {
+            if !in_bounds(bounds, next) {
+                continue;
+            }
+            let step_cost = if backwards {
+                let length = if next.0 != cell.0 && next.1 != cell.1 { f32::consts::SQRT_2 } else { 1.0 };
+                cell_cost(tiles, cell.0 as isize, cell.1 as isize).unwrap_or(f32::INFINITY) * length
+            } else {
+                step_cost
+            };
+            let next_idx = search.index(next);
+            let cost = search.costs[cell_idx] + step_cost;
+            if cost < search.costs[next_idx] {
+                search.costs[next_idx] = cost;
+                search.came_from[next_idx] = Some(cell_idx);
+                open.push(Node { estimate: cost + estimate(next), cell_idx: next_idx });
+            }
+        }
+    }
+    search
+}
+
+/// Finds the cheapest route between two cells with A*.
+///
+/// The route excludes `from` and ends with `to`. Returns `None` if `to` can't be reached.
+pub fn find_path(tiles: &Tiles, from: (usize, usize), to: (usize, usize)) -> Option<Vec<(usize, usize)>> {
+    find_path_within(tiles, from, to, map_bounds(tiles))
+}
+
+/// Same as `find_path`, without leaving the bounds.
+pub fn find_path_within(tiles: &Tiles, from: (usize, usize), to: (usize, usize), bounds: Bounds)
+    -> Option<Vec<(usize, usize)>>
+{
+    if !in_bounds(bounds, from) || !in_bounds(bounds, to) || tile_cost(tiles.ground_at(to).tex_id).is_none() {
+        return None;
+    }
+    search(tiles, from, Some(to), bounds).path_to(to)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: f32, b: f32) -> bool {
+        (a - b).abs() < 0.001
+    }
+
+    #[test]
+    fn straight_path() {
+        let tiles = Tiles::from_rows(&[
+            ".....",
+            ".....",
+        ]);
+        let path = find_path(&tiles, (0, 0), (4, 0)).unwrap();
+        assert_eq!(path, vec![(1, 0), (2, 0), (3, 0), (4, 0)]);
+        assert!(close(route_cost(&tiles, (0, 0), &path), 4.0));
+        assert_eq!(find_path(&tiles, (2, 1), (2, 1)), Some(vec![]));
+    }
+
+    #[test]
+    fn around_a_wall() {
+        let tiles = Tiles::from_rows(&[
+            "..#..",
+            "..#..",
+            ".....",
+        ]);
+        let path = find_path(&tiles, (0, 0), (4, 0)).unwrap();
+        assert!(path.iter().all(|&cell| tiles::is_walkable(tiles.ground_at(cell).tex_id)));
+        assert_eq!(path.last(), Some(&(4, 0)));
+        // down the side, past the end of the wall without cutting its corner and back up
+        assert!(close(route_cost(&tiles, (0, 0), &path), 4.0 + 2.0 * f32::consts::SQRT_2));
+    }
+
+    #[test]
+    fn clay_is_avoided() {
+        let tiles = Tiles::from_rows(&[
+            ".....",
+            ",,,,,",
+        ]);
+        let path = find_path(&tiles, (0, 1), (4, 1)).unwrap();
+        assert!(!path.contains(&(2, 1)));
+        assert!(close(route_cost(&tiles, (0, 1), &path), 2.0 + 3.0 * f32::consts::SQRT_2));
+    }
+
+    #[test]
+    fn no_path() {
+        let tiles = Tiles::from_rows(&[
+            "..#..",
+            "..#.~",
+        ]);
+        assert_eq!(find_path(&tiles, (0, 0), (4, 0)), None);
+        assert_eq!(find_path(&tiles, (0, 0), (2, 0)), None);
+        assert_eq!(find_path(&tiles, (3, 0), (4, 1)), None);
+        assert_eq!(find_path_within(&tiles, (3, 0), (4, 0), ((0, 0), (4, 2))), None);
+    }
+
+    #[test]
+    fn no_cutting_corners() {
+        let tiles = Tiles::from_rows(&[
+            ".#",
+            "..",
+        ]);
+        let cells = neighbours(&tiles, (0, 0)).iter().map(|&(cell, _)| cell).collect::<Vec<_>>();
+        assert_eq!(cells, vec![(0, 1)]);
+    }
+
+    #[test]
+    fn heuristic_is_admissible() {
+        let tiles = Tiles::from_rows(&[
+            ".,..,.",
+            "..#...",
+            ".,#.,.",
+            "......",
+        ]);
+        let search = search(&tiles, (0, 0), None, map_bounds(&tiles));
+        for x in 0..tiles.width {
+            for y in 0..tiles.height {
+                if let Some(cost) = search.cost((x, y)) {
+                    assert!(heuristic((0, 0), (x, y)) <= cost + 0.001);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn search_back_costs_the_way_to_the_target() {
+        let tiles = Tiles::from_rows(&[
+            ",..,.",
+            "..,..",
+        ]);
+        let back = search_back(&tiles, (4, 1), map_bounds(&tiles));
+        for x in 0..tiles.width {
+            for y in 0..tiles.height {
+                let path = find_path(&tiles, (x, y), (4, 1)).unwrap();
+                assert!(close(back.cost((x, y)).unwrap(), route_cost(&tiles, (x, y), &path)));
+            }
+        }
+    }
+
+    #[test]
+    fn search_stops_at_the_targets() {
+        let tiles = Tiles::from_rows(&[
+            "..,.......",
+            ".#,..#....",
+            "..,.......",
+        ]);
+        let targets = [(1, 0), (3, 2)];
+        let full = search(&tiles, (0, 1), None, map_bounds(&tiles));
+        let part = search_to_all(&tiles, (0, 1), &targets, map_bounds(&tiles));
+        for &target in targets.iter() {
+            assert!(close(part.cost(target).unwrap(), full.cost(target).unwrap()));
+        }
+        assert_eq!(part.cost((9, 0)), None);
+    }
+}
@@ -0,0 +1,204 @@
+use rand;
+use rand::Rng;
+use cgmath::Vector2;
+use identity::Identity;
+use jobs;
+use miners::Miners;
+use social;
+use stockpiles::Stockpiles;
+use tiles::{Tile, Tiles};
+
+/// Seconds between waves of migrants
+const WAVE_INTERVAL: f32 = 900.0;
+/// Stored resources that draw one more migrant per wave
+const WEALTH_PER_MIGRANT: f32 = 100.0;
+const MAX_WAVE: usize = 8;
+/// Chance per second of a couple having a child
+const BIRTH_RATE: f32 = 1.0 / 3600.0;
+/// Seconds in a year, after which everyone is a year older
+const YEAR: f32 = 1200.0;
+/// Portions of drink every newcomer brings along
+const DRINK_PER_SETTLER: usize = 12;
+
+/// Brings new miners into the colony: migrants arriving in waves and children born to couples.
+pub struct Population {
+    /// Seconds until the next wave of migrants
+    pub next_wave: f32,
+    pub waves: u32,
+    /// Seconds until the end of the year
+    pub year_left: f32,
+}
+
+impl Population {
+    pub fn new() -> Population {
+        Population {
+            next_wave: WAVE_INTERVAL,
+            waves: 0,
+            year_left: YEAR,
+        }
+    }
+
+    /// How many migrants a wave brings: more to a wealthy colony, fewer to a miserable one.
+    pub fn wave_size(&self, miners: &Miners, tiles: &Tiles, stockpiles: &Stockpiles) -> usize {
+        let wealth = stockpiles.totals(tiles).values().sum::<u32>() as f32;
+        let mood = if miners.miners.len() > 0 {
+            miners.miners.iter().map(|miner| miner.mood.score()).sum::<f32>() / miners.miners.len() as f32
+        } else {
+            0.0
+        };
+        let size = (1.0 + wealth / WEALTH_PER_MIGRANT) * (1.0 + mood / 100.0);
+        (size.round().max(0.0) as usize).min(MAX_WAVE)
+    }
+
+    pub fn update(&mut self, duration: f32, miners: &mut Miners, tiles: &mut Tiles, stockpiles: &Stockpiles) {
+        self.next_wave -= duration;
+        if self.next_wave <= 0.0 {
+            self.next_wave = WAVE_INTERVAL;
+            let size = self.wave_size(miners, tiles, stockpiles);
+            self.arrive(size, miners, tiles);
+        }
+        self.year_left -= duration;
+        if self.year_left <= 0.0 {
+            self.year_left = YEAR;
+            grow_older(miners);
+        }
+        self.give_birth(duration, miners);
+    }
+
+    /// Migrants walk in together at some spot on the edge of the map.
+    fn arrive(&mut self, size: usize, miners: &mut Miners, tiles: &mut Tiles) {
+        let edge = tiles.edge_walkable();
+        let cell = match rand::thread_rng().choose(&edge) {
+            Some(&cell) if size > 0 => cell,
+            _ => return,
+        };
+        self.waves += 1;
+        println!("Wave {}: {} migrants arrive at {:?}", self.waves, size, cell);
+        for _ in 0..size {
+            miners.arrive(tiles.cell_position(cell), Identity::generate());
+        }
+        bring_provisions(tiles, cell, size);
+    }
+
+    fn give_birth(&mut self, duration: f32, miners: &mut Miners) {
+        let mut births: Vec<(Vector2<f32>, Identity)> = Vec::new();
+        for miner in miners.miners.iter() {
+            let partner = match miners.relationships.spouse_of(miner.id).and_then(|id| miners.get(id)) {
+                // each couple is looked at once
+                Some(partner) if partner.id > miner.id => partner,
+                _ => continue,
+            };
+            let adults = miner.identity.age >= social::ADULT_AGE && partner.identity.age >= social::ADULT_AGE;
+            if adults && rand::random::<f32>() < BIRTH_RATE * duration {
+                println!("{} and {} had a child", miner.identity.name, partner.identity.name);
+                births.push((miner.tile.position, Identity::born_to(&miner.identity, &partner.identity)));
+            }
+        }
+        for (position, identity) in births {
+            miners.arrive(position, identity);
+        }
+    }
+}
+
+/// Leaves a cask with enough drink for some newcomers at a cell, to tide them over.
+///
+/// Returns the index of the cask.
+pub fn bring_provisions(tiles: &mut Tiles, cell: (usize, usize), settlers: usize) -> usize {
+    let mut cask = Tile::new(tiles.cell_position(cell), ::SPRITE_DRINK, Some(::RESOURCE_DRINK));
+    cask.resource_count = (settlers * DRINK_PER_SETTLER).min(u8::max_value() as usize) as u8;
+    cask.can_be_carried = true;
+    tiles.spawn(cask)
+}
+
+/// Everyone is a year older. Children coming of age start to work.
+fn grow_older(miners: &mut Miners) {
+    for miner in miners.miners.iter_mut() {
+        miner.identity.age += 1;
+        if miner.identity.age == social::ADULT_AGE {
+            miner.labours = jobs::JOB_KINDS.to_vec();
+            println!("{} came of age", miner.identity.name);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mood::{Mood, ThoughtKind};
+
+    fn colony(count: u8) -> (Tiles, Miners, Stockpiles) {
+        let tiles = Tiles::from_rows(&[
+            "......",
+            "......",
+            "......",
+        ]);
+        let miners = Miners::new(count, &tiles).unwrap();
+        (tiles, miners, Stockpiles::new())
+    }
+
+    /// Stores `count` piles of five stones on the first row.
+    fn store(tiles: &mut Tiles, stockpiles: &mut Stockpiles, count: usize) {
+        let cells = (0..6).map(|x| (x, 0)).collect::<Vec<_>>();
+        stockpiles.designate(tiles, &cells, vec![::RESOURCE_STONE], 100);
+        for i in 0..count {
+            let position = tiles.cell_position(cells[i % cells.len()]);
+            tiles.spawn(Tile::new(position, ::SPRITE_ROCK, Some(::RESOURCE_STONE)));
+        }
+    }
+
+    #[test]
+    fn wave_size() {
+        let population = Population::new();
+        let (mut tiles, mut miners, mut stockpiles) = colony(4);
+        assert_eq!(population.wave_size(&miners, &tiles, &stockpiles), 1);
+        // 100 stones draw one more
+        store(&mut tiles, &mut stockpiles, 20);
+        assert_eq!(population.wave_size(&miners, &tiles, &stockpiles), 2);
+        // half as many to a miserable colony
+        store(&mut tiles, &mut stockpiles, 40);
+        for miner in miners.miners.iter_mut() {
+            let identity = miner.identity.clone();
+            for _ in 0..3 {
+                miner.mood.add(ThoughtKind::SpouseDied, &identity);
+            }
+        }
+        assert_eq!(population.wave_size(&miners, &tiles, &stockpiles), 0);
+        for miner in miners.miners.iter_mut() {
+            miner.mood = Mood::new();
+            let identity = miner.identity.clone();
+            miner.mood.add(ThoughtKind::WentHungry, &identity);
+            miner.mood.add(ThoughtKind::WentThirsty, &identity);
+        }
+        assert_eq!(population.wave_size(&miners, &tiles, &stockpiles), 3);
+        // never more than a crowd
+        store(&mut tiles, &mut stockpiles, 200);
+        assert_eq!(population.wave_size(&miners, &tiles, &stockpiles), MAX_WAVE);
+    }
+
+    #[test]
+    fn waves_arrive() {
+        let mut population = Population::new();
+        let (mut tiles, mut miners, stockpiles) = colony(2);
+        population.update(WAVE_INTERVAL, &mut miners, &mut tiles, &stockpiles);
+        assert_eq!(population.waves, 1);
+        assert_eq!(miners.miners.len(), 3);
+        // the migrant brought a cask of drink along
+        let cask = tiles.tiles.last().unwrap();
+        assert_eq!((cask.tex_id, cask.resource_count), (::SPRITE_DRINK, DRINK_PER_SETTLER as u8));
+        let ids = miners.miners.iter().map(|miner| miner.id).collect::<Vec<_>>();
+        assert_eq!(ids, vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn children_grow_up() {
+        let (tiles, mut miners, _) = colony(1);
+        let mut child = Identity::born_to(&miners.miners[0].identity, &miners.miners[0].identity);
+        child.age = social::ADULT_AGE - 1;
+        let id = miners.arrive(tiles.cell_position((1, 1)), child);
+        assert!(miners.get(id).unwrap().labours.is_empty());
+        grow_older(&mut miners);
+        let grown = miners.get(id).unwrap();
+        assert_eq!(grown.identity.age, social::ADULT_AGE);
+        assert_eq!(grown.labours, jobs::JOB_KINDS.to_vec());
+    }
+}
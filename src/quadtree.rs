@@ -169,6 +169,21 @@ impl QuadTree {
         None
     }
 
+    /// Same as `remove`, only looking in the branches around the position.
+    pub fn remove_at(&mut self, pos: &Vector2<f32>, id: usize) {
+        if !self.contains(pos) {
+            return;
+        }
+        if self.branches.len() == 0 {
+            self.tiles.retain(|&x| x != id);
+            self.tiles_set.remove(&id);
+        } else {
+            for branch in self.branches.iter_mut() {
+                branch.remove_at(pos, id);
+            }
+        }
+    }
+
     pub fn remove(&mut self, id: usize) {
         if self.branches.len() == 0 && self.tiles.len() > 0 {
             let old_len = self.tiles.len();
@@ -0,0 +1,126 @@
+use std::collections::HashMap;
+use tiles::TileEvent;
+
+/// Something only one miner at a time may work on.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub enum Target {
+    Job(usize),
+    /// a tree, a wall, an item on the ground
+    Tile(usize),
+    /// room for one more item on a stockpile cell, by ground tile and number
+    Slot(usize, usize),
+}
+
+/// Claims of miners on jobs, tiles and items, keyed by what is claimed.
+pub struct Reservations {
+    owners: HashMap<Target, usize>,
+}
+
+impl Reservations {
+    pub fn new() -> Reservations {
+        Reservations {
+            owners: HashMap::new(),
+        }
+    }
+
+    /// Id of the miner holding a claim on the target.
+    pub fn owner(&self, target: Target) -> Option<usize> {
+        self.owners.get(&target).map(|&miner_id| miner_id)
+    }
+
+    /// Whether someone other than the miner holds a claim on the target.
+    pub fn is_taken(&self, target: Target, miner_id: usize) -> bool {
+        self.owner(target).map_or(false, |owner| owner != miner_id)
+    }
+
+    /// Claims the target for a miner, unless someone else already has.
+    pub fn reserve(&mut self, target: Target, miner_id: usize) -> bool {
+        if self.is_taken(target, miner_id) {
+            return false;
+        }
+        self.owners.insert(target, miner_id);
+        true
+    }
+
+    pub fn release(&mut self, target: Target) {
+        self.owners.remove(&target);
+    }
+
+    /// Drops every claim of a miner, when it stops working, gets interrupted or dies.
+    pub fn release_all(&mut self, miner_id: usize) {
+        self.owners.retain(|_, &mut owner| owner != miner_id);
+    }
+
+    /// Passes every claim of a miner on to another one, e.g. with the item it was hauling.
+    pub fn hand_over(&mut self, from: usize, to: usize) {
+        for owner in self.owners.values_mut().filter(|owner| **owner == from) {
+            *owner = to;
+        }
+    }
+
+    /// Drops the claims on tiles that are gone.
+    pub fn handle_events(&mut self, events: &[TileEvent]) {
+        for event in events.iter() {
+            if let TileEvent::Removed(id) = *event {
+                self.release(Target::Tile(id));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn one_owner_at_a_time() {
+        let mut reservations = Reservations::new();
+        assert!(reservations.reserve(Target::Job(1), 7));
+        // claiming again is harmless, taking it from someone else isn't allowed
+        assert!(reservations.reserve(Target::Job(1), 7));
+        assert!(!reservations.reserve(Target::Job(1), 8));
+        assert_eq!(reservations.owner(Target::Job(1)), Some(7));
+        assert!(reservations.is_taken(Target::Job(1), 8));
+        assert!(!reservations.is_taken(Target::Job(1), 7));
+        // a job and a tile with the same index are different targets
+        assert!(reservations.reserve(Target::Tile(1), 8));
+        reservations.release(Target::Job(1));
+        assert!(reservations.reserve(Target::Job(1), 8));
+    }
+
+    #[test]
+    fn released_with_the_miner() {
+        let mut reservations = Reservations::new();
+        reservations.reserve(Target::Job(1), 7);
+        reservations.reserve(Target::Tile(2), 7);
+        reservations.reserve(Target::Tile(3), 8);
+        reservations.release_all(7);
+        assert_eq!(reservations.owner(Target::Job(1)), None);
+        assert_eq!(reservations.owner(Target::Tile(2)), None);
+        assert_eq!(reservations.owner(Target::Tile(3)), Some(8));
+    }
+
+    #[test]
+    fn handed_over() {
+        let mut reservations = Reservations::new();
+        reservations.reserve(Target::Job(1), 7);
+        reservations.reserve(Target::Slot(2, 0), 7);
+        reservations.reserve(Target::Tile(3), 8);
+        reservations.hand_over(7, 9);
+        assert_eq!(reservations.owner(Target::Job(1)), Some(9));
+        assert_eq!(reservations.owner(Target::Slot(2, 0)), Some(9));
+        assert_eq!(reservations.owner(Target::Tile(3)), Some(8));
+    }
+
+    #[test]
+    fn released_with_the_tile() {
+        let mut reservations = Reservations::new();
+        reservations.reserve(Target::Tile(2), 7);
+        reservations.reserve(Target::Tile(3), 7);
+        reservations.reserve(Target::Job(2), 7);
+        reservations.handle_events(&[TileEvent::Changed(3), TileEvent::Removed(2)]);
+        assert_eq!(reservations.owner(Target::Tile(2)), None);
+        assert_eq!(reservations.owner(Target::Tile(3)), Some(7));
+        assert_eq!(reservations.owner(Target::Job(2)), Some(7));
+    }
+}
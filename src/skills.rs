@@ -0,0 +1,100 @@
+/// Experience needed for the first level; level `n` takes `n * n` times as much
+const XP_PER_LEVEL: f32 = 100.0;
+const MAX_LEVEL: u32 = 20;
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Skill {
+    Woodcutting,
+    Mining,
+    Hauling,
+    Building,
+}
+
+pub const SKILLS: [Skill; 4] = [Skill::Woodcutting, Skill::Mining, Skill::Hauling, Skill::Building];
+
+/// Experience a miner gathered in every skill.
+pub struct Skills {
+    xp: [f32; 4],
+}
+
+impl Skills {
+    pub fn new() -> Skills {
+        Skills {
+            xp: [0.0; 4],
+        }
+    }
+
+    fn index(skill: Skill) -> usize {
+        SKILLS.iter().position(|&s| s == skill).unwrap()
+    }
+
+    pub fn xp(&self, skill: Skill) -> f32 {
+        self.xp[Skills::index(skill)]
+    }
+
+    pub fn level(&self, skill: Skill) -> u32 {
+        ((self.xp(skill) / XP_PER_LEVEL).sqrt() as u32).min(MAX_LEVEL)
+    }
+
+    /// Adds experience to a skill. Returns the new level if it went up.
+    pub fn gain(&mut self, skill: Skill, xp: f32) -> Option<u32> {
+        let level = self.level(skill);
+        self.xp[Skills::index(skill)] += xp;
+        if self.level(skill) > level { Some(self.level(skill)) } else { None }
+    }
+
+    /// Share of the usual time a job takes, every level cutting a bit more off.
+    pub fn duration_factor(&self, skill: Skill) -> f32 {
+        1.0 / (1.0 + 0.1 * self.level(skill) as f32)
+    }
+
+    /// Resource portions gained on top of the usual yield.
+    pub fn yield_bonus(&self, skill: Skill) -> u8 {
+        (self.level(skill) / 4) as u8
+    }
+
+    /// Share of the slow-down of a load that is still felt, less for a trained hauler.
+    pub fn load_factor(&self) -> f32 {
+        1.0 - 0.04 * self.level(Skill::Hauling) as f32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn levels_from_xp() {
+        let mut skills = Skills::new();
+        assert_eq!(skills.level(Skill::Mining), 0);
+        assert_eq!(skills.gain(Skill::Mining, 99.0), None);
+        assert_eq!(skills.gain(Skill::Mining, 1.0), Some(1));
+        // the second level takes four times as much
+        assert_eq!(skills.gain(Skill::Mining, 299.0), None);
+        assert_eq!(skills.gain(Skill::Mining, 1.0), Some(2));
+        assert_eq!(skills.xp(Skill::Mining), 400.0);
+        // every skill is trained on its own
+        assert_eq!(skills.level(Skill::Building), 0);
+        skills.gain(Skill::Building, 1.0e6);
+        assert_eq!(skills.level(Skill::Building), MAX_LEVEL);
+        assert_eq!(skills.gain(Skill::Building, 1.0e6), None);
+    }
+
+    #[test]
+    fn trained_hands() {
+        let mut skills = Skills::new();
+        assert_eq!(skills.duration_factor(Skill::Woodcutting), 1.0);
+        assert_eq!(skills.yield_bonus(Skill::Woodcutting), 0);
+        assert_eq!(skills.load_factor(), 1.0);
+        // level 4
+        skills.gain(Skill::Woodcutting, 1600.0);
+        assert!((skills.duration_factor(Skill::Woodcutting) - 1.0 / 1.4).abs() < 1e-6);
+        assert_eq!(skills.yield_bonus(Skill::Woodcutting), 1);
+        assert_eq!(skills.yield_bonus(Skill::Mining), 0);
+        // level 10
+        skills.gain(Skill::Hauling, 10000.0);
+        assert!((skills.load_factor() - 0.6).abs() < 1e-6);
+        assert_eq!(skills.duration_factor(Skill::Hauling), 0.5);
+        assert_eq!(skills.yield_bonus(Skill::Hauling), 2);
+    }
+}
@@ -0,0 +1,254 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+use rand;
+
+/// Opinion from which miners count as friends
+const FRIEND_OPINION: f32 = 30.0;
+/// Opinion below which miners count as rivals
+const RIVAL_OPINION: f32 = -30.0;
+/// Opinion close friends need before they may pair up
+const SPOUSE_OPINION: f32 = 80.0;
+/// Chance per second of close friends pairing up
+const PAIRING_RATE: f32 = 0.01;
+/// Age from which miners may pair up
+pub const ADULT_AGE: u32 = 18;
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Bond {
+    Acquaintance,
+    Friend,
+    Rival,
+    Spouse,
+}
+
+impl Bond {
+    fn name(&self) -> &'static str {
+        match *self {
+            Bond::Acquaintance => "acquaintance",
+            Bond::Friend => "friend",
+            Bond::Rival => "rival",
+            Bond::Spouse => "spouse",
+        }
+    }
+}
+
+impl FromStr for Bond {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Bond, String> {
+        match name {
+            "acquaintance" => Ok(Bond::Acquaintance),
+            "friend" => Ok(Bond::Friend),
+            "rival" => Ok(Bond::Rival),
+            "spouse" => Ok(Bond::Spouse),
+            _ => Err(format!("Unknown bond '{}'", name)),
+        }
+    }
+}
+
+/// How two miners get on.
+#[derive(Copy, Clone, Debug)]
+pub struct Relationship {
+    pub bond: Bond,
+    /// From -100 (can't stand each other) to 100
+    pub opinion: f32,
+    /// How well they click, from -1 to 1, set when they first meet: the opinion drifts this way
+    pub compatibility: f32,
+}
+
+/// Who knows whom, an edge per pair of miners that met.
+pub struct Relationships {
+    edges: HashMap<(usize, usize), Relationship>,
+}
+
+/// Edges are kept once per pair, lower id first.
+fn key(a: usize, b: usize) -> (usize, usize) {
+    if a < b { (a, b) } else { (b, a) }
+}
+
+impl Relationships {
+    pub fn new() -> Relationships {
+        Relationships {
+            edges: HashMap::new(),
+        }
+    }
+
+    pub fn get(&self, a: usize, b: usize) -> Option<&Relationship> {
+        self.edges.get(&key(a, b))
+    }
+
+    pub fn bond(&self, a: usize, b: usize) -> Option<Bond> {
+        self.get(a, b).map(|relationship| relationship.bond)
+    }
+
+    /// Everyone the miner knows and how they get on.
+    pub fn of(&self, id: usize) -> Vec<(usize, Relationship)> {
+        self.edges.iter()
+            .filter(|&(&(a, b), _)| a == id || b == id)
+            .map(|(&(a, b), &relationship)| (if a == id { b } else { a }, relationship))
+            .collect()
+    }
+
+    /// The miner's spouse, if any.
+    pub fn spouse_of(&self, id: usize) -> Option<usize> {
+        self.of(id).iter().find(|&&(_, relationship)| relationship.bond == Bond::Spouse).map(|&(other, _)| other)
+    }
+
+    /// Two miners spend time together, `closeness` per second, more when it's a meal than a chat.
+    ///
+    /// Returns the new bond when it changed.
+    pub fn interact(&mut self, a: usize, b: usize, closeness: f32, duration: f32) -> Option<Bond> {
+        let relationship = self.edges.entry(key(a, b)).or_insert_with(|| Relationship {
+            bond: Bond::Acquaintance,
+            opinion: 0.0,
+            compatibility: rand::random::<f32>() * 2.0 - 1.0,
+        });
+        relationship.opinion = (relationship.opinion + relationship.compatibility * closeness * duration)
+            .max(-100.0).min(100.0);
+        let bond = match relationship.bond {
+            // partners stay partners however they get on
+            Bond::Spouse => Bond::Spouse,
+            _ if relationship.opinion >= FRIEND_OPINION => Bond::Friend,
+            _ if relationship.opinion <= RIVAL_OPINION => Bond::Rival,
+            _ => Bond::Acquaintance,
+        };
+        if bond == relationship.bond {
+            return None;
+        }
+        relationship.bond = bond;
+        Some(bond)
+    }
+
+    /// Whether two miners could pair up, given their ages.
+    pub fn could_pair(&self, a: (usize, u32), b: (usize, u32)) -> bool {
+        a.1 >= ADULT_AGE && b.1 >= ADULT_AGE
+            && self.get(a.0, b.0).map_or(false, |r| r.bond == Bond::Friend && r.opinion >= SPOUSE_OPINION)
+            && self.spouse_of(a.0).is_none() && self.spouse_of(b.0).is_none()
+    }
+
+    /// Close friends spending time together may pair up. Returns true when they do.
+    pub fn court(&mut self, a: (usize, u32), b: (usize, u32), duration: f32) -> bool {
+        if !self.could_pair(a, b) || rand::random::<f32>() >= PAIRING_RATE * duration {
+            return false;
+        }
+        self.edges.get_mut(&key(a.0, b.0)).unwrap().bond = Bond::Spouse;
+        true
+    }
+
+    /// Drops every edge of a miner that is gone.
+    pub fn forget(&mut self, id: usize) {
+        self.edges.retain(|&(a, b), _| a != id && b != id);
+    }
+}
+
+/// One edge per line: `a b bond opinion compatibility`.
+impl fmt::Display for Relationships {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut keys = self.edges.keys().collect::<Vec<_>>();
+        keys.sort();
+        for &&(a, b) in keys.iter() {
+            let r = self.edges[&(a, b)];
+            writeln!(f, "{} {} {} {} {}", a, b, r.bond.name(), r.opinion, r.compatibility)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Relationships {
+    type Err = String;
+
+    fn from_str(source: &str) -> Result<Relationships, String> {
+        let mut relationships = Relationships::new();
+        for (number, line) in source.lines().enumerate().filter(|&(_, line)| line.trim().len() > 0) {
+            let fields = line.split_whitespace().collect::<Vec<_>>();
+            if fields.len() != 5 {
+                return Err(format!("Line {}: expected 5 fields, found {}", number + 1, fields.len()));
+            }
+            let error = |field: &str| format!("Line {}: bad {} '{}'", number + 1, field, line);
+            let a = fields[0].parse::<usize>().map_err(|_| error("id"))?;
+            let b = fields[1].parse::<usize>().map_err(|_| error("id"))?;
+            relationships.edges.insert(key(a, b), Relationship {
+                bond: fields[2].parse()?,
+                opinion: fields[3].parse().map_err(|_| error("opinion"))?,
+                compatibility: fields[4].parse().map_err(|_| error("compatibility"))?,
+            });
+        }
+        Ok(relationships)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn with(edges: &[(usize, usize, Bond, f32, f32)]) -> Relationships {
+        let mut relationships = Relationships::new();
+        for &(a, b, bond, opinion, compatibility) in edges.iter() {
+            relationships.edges.insert(key(a, b), Relationship {
+                bond: bond,
+                opinion: opinion,
+                compatibility: compatibility,
+            });
+        }
+        relationships
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut relationships = with(&[(3, 1, Bond::Spouse, 92.5, 0.75), (0, 2, Bond::Rival, -41.25, -0.5)]);
+        for id in 4..8 {
+            relationships.interact(0, id, 2.0, 3.0);
+        }
+        let text = relationships.to_string();
+        let parsed = text.parse::<Relationships>().unwrap();
+        assert_eq!(parsed.to_string(), text);
+        assert_eq!(parsed.edges.len(), 6);
+        let spouse = parsed.get(1, 3).unwrap();
+        assert_eq!((spouse.bond, spouse.opinion, spouse.compatibility), (Bond::Spouse, 92.5, 0.75));
+        assert_eq!(text.lines().next(), Some("0 2 rival -41.25 -0.5"));
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert!("0 1 friend 40".parse::<Relationships>().is_err());
+        assert!("0 x friend 40 0.5".parse::<Relationships>().is_err());
+        assert!("0 1 lover 40 0.5".parse::<Relationships>().is_err());
+        assert!("0 1 friend lots 0.5".parse::<Relationships>().is_err());
+        assert_eq!("\n\n".parse::<Relationships>().map(|r| r.edges.len()), Ok(0));
+    }
+
+    #[test]
+    fn opinion_sets_the_bond() {
+        let mut relationships = with(&[(0, 1, Bond::Acquaintance, 25.0, 1.0), (0, 2, Bond::Acquaintance, -25.0, -1.0)]);
+        assert_eq!(relationships.interact(1, 0, 2.0, 3.0), Some(Bond::Friend));
+        assert_eq!(relationships.interact(0, 1, 2.0, 2.0), None);
+        assert_eq!(relationships.interact(0, 2, 2.0, 3.0), Some(Bond::Rival));
+        // opinions stay within bounds
+        relationships.interact(0, 1, 100.0, 10.0);
+        assert_eq!(relationships.get(0, 1).unwrap().opinion, 100.0);
+        // strangers may get on as badly as they may get on well
+        for id in 3..53 {
+            relationships.interact(0, id, 0.0, 0.0);
+            let compatibility = relationships.get(0, id).unwrap().compatibility;
+            assert!(compatibility >= -1.0 && compatibility < 1.0);
+        }
+    }
+
+    #[test]
+    fn couples() {
+        let mut relationships = with(&[(0, 1, Bond::Friend, 90.0, 1.0), (1, 2, Bond::Friend, 90.0, 1.0)]);
+        assert!(!relationships.could_pair((0, ADULT_AGE - 1), (1, 30)));
+        assert!(relationships.could_pair((0, ADULT_AGE), (1, 30)));
+        while !relationships.court((0, 30), (1, 30), 1.0) {}
+        assert_eq!(relationships.spouse_of(0), Some(1));
+        assert_eq!(relationships.spouse_of(1), Some(0));
+        // one spouse at a time, and spouses stay spouses however they get on
+        assert!(!relationships.could_pair((1, 30), (2, 30)));
+        relationships.interact(0, 1, -100.0, 10.0);
+        assert_eq!(relationships.bond(0, 1), Some(Bond::Spouse));
+        relationships.forget(1);
+        assert_eq!(relationships.spouse_of(0), None);
+        assert!(relationships.of(2).is_empty());
+    }
+}
@@ -0,0 +1,199 @@
+use std::collections::HashMap;
+use pathfinding::Search;
+use reservations::{Reservations, Target};
+use tiles;
+use tiles::Tiles;
+
+/// A zone of cells the player set aside for storing some kinds of items.
+pub struct Stockpile {
+    pub cells: Vec<(usize, usize)>,
+    /// Resources stored here, anything when empty
+    pub accepts: Vec<u8>,
+    /// How many items fit on one cell
+    pub capacity: usize,
+}
+
+impl Stockpile {
+    pub fn accepts(&self, resource_id: Option<u8>) -> bool {
+        self.accepts.len() == 0 || resource_id.map_or(false, |id| self.accepts.contains(&id))
+    }
+}
+
+pub struct Stockpiles {
+    pub zones: Vec<Stockpile>,
+}
+
+impl Stockpiles {
+    pub fn new() -> Stockpiles {
+        Stockpiles {
+            zones: Vec::new(),
+        }
+    }
+
+    /// Turns the walkable cells among the given ones into a new zone.
+    ///
+    /// Cells that belonged to another zone move over to the new one. Returns the index of the zone.
+    pub fn designate(&mut self, tiles: &Tiles, cells: &[(usize, usize)], accepts: Vec<u8>, capacity: usize)
+        -> Option<usize>
+    {
+        let cells = cells.iter()
+            .filter(|&&cell| tiles::is_walkable(tiles.ground_at(cell).tex_id))
+            .map(|&cell| cell)
+            .collect::<Vec<_>>();
+        if cells.len() == 0 {
+            return None;
+        }
+        for zone in self.zones.iter_mut() {
+            zone.cells.retain(|cell| !cells.contains(cell));
+        }
+        self.zones.retain(|zone| zone.cells.len() > 0);
+        println!("Stockpile of {} cells for {:?}, {} per cell", cells.len(), accepts, capacity);
+        self.zones.push(Stockpile {
+            cells: cells,
+            accepts: accepts,
+            capacity: capacity.max(1),
+        });
+        Some(self.zones.len() - 1)
+    }
+
+    /// Index of the zone a cell belongs to.
+    pub fn zone_at(&self, cell: (usize, usize)) -> Option<usize> {
+        self.zones.iter().position(|zone| zone.cells.contains(&cell))
+    }
+
+    pub fn contains(&self, cell: (usize, usize)) -> bool {
+        self.zone_at(cell).is_some()
+    }
+
+    /// Whether an item lies on a stockpile that takes it.
+    pub fn is_stored(&self, tiles: &Tiles, item: usize) -> bool {
+        tiles.cell_at(tiles.tiles[item].position)
+            .and_then(|cell| self.zone_at(cell))
+            .map_or(false, |zone| self.zones[zone].accepts(tiles.tiles[item].resource_id))
+    }
+
+    /// Finds the cell closest to walk to from the origin of the search that takes the item and has room left,
+    /// and a free slot on it to reserve.
+    ///
+    /// Items others are bringing over count against the room of a cell.
+    pub fn find_free(&self, tiles: &Tiles, reservations: &Reservations, miner_id: usize,
+                     search: &Search, item: usize) -> Option<((usize, usize), Target)> {
+        let resource_id = tiles.tiles[item].resource_id;
+        let mut best: Option<(f32, (usize, usize), Target)> = None;
+        for zone in self.zones.iter().filter(|zone| zone.accepts(resource_id)) {
+            for &cell in zone.cells.iter() {
+                let cost = match search.cost(cell) {
+                    Some(cost) if best.map_or(true, |(c, _, _)| cost < c) => cost,
+                    _ => continue,
+                };
+                let ground = tiles.ground[tiles.cell_index(cell)];
+                let slots = (0..zone.capacity).map(|slot| Target::Slot(ground, slot)).collect::<Vec<_>>();
+                let coming = slots.iter().filter(|&&slot| reservations.is_taken(slot, miner_id)).count();
+                if tiles.items_at(cell).len() + coming >= zone.capacity {
+                    continue;
+                }
+                let slot = slots.into_iter().find(|&slot| !reservations.is_taken(slot, miner_id)).unwrap();
+                best = Some((cost, cell, slot));
+            }
+        }
+        best.map(|(_, cell, slot)| (cell, slot))
+    }
+
+    /// Amount of every resource kept on the stockpiles.
+    pub fn totals(&self, tiles: &Tiles) -> HashMap<u8, u32> {
+        let mut totals = HashMap::new();
+        for zone in self.zones.iter() {
+            for &cell in zone.cells.iter() {
+                for id in tiles.items_at(cell) {
+                    let tile = &tiles.tiles[id];
+                    if tile.resource_id.is_some() && zone.accepts(tile.resource_id) {
+                        *totals.entry(tile.resource_id.unwrap()).or_insert(0) += tile.resource_count as u32;
+                    }
+                }
+            }
+        }
+        totals
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pathfinding;
+    use tiles::Tile;
+
+    fn drop_item(tiles: &mut Tiles, cell: (usize, usize), tex_id: u32, resource_id: u8) -> usize {
+        let mut item = Tile::new(tiles.cell_position(cell), tex_id, Some(resource_id));
+        item.can_be_carried = true;
+        tiles.spawn(item)
+    }
+
+    #[test]
+    fn filters() {
+        let tiles = Tiles::from_rows(&[
+            "...#",
+            "....",
+        ]);
+        let mut stockpiles = Stockpiles::new();
+        assert_eq!(stockpiles.designate(&tiles, &[(3, 0)], vec![], 1), None);
+        stockpiles.designate(&tiles, &[(0, 0), (1, 0), (3, 0)], vec![::RESOURCE_STONE], 1);
+        assert_eq!(stockpiles.zones[0].cells, vec![(0, 0), (1, 0)]);
+        assert!(stockpiles.zones[0].accepts(Some(::RESOURCE_STONE)));
+        assert!(!stockpiles.zones[0].accepts(Some(::RESOURCE_FOOD)));
+        assert!(!stockpiles.zones[0].accepts(None));
+        // a cell moves over to the newer zone, which takes anything
+        stockpiles.designate(&tiles, &[(1, 0)], vec![], 1);
+        assert_eq!(stockpiles.zone_at((0, 0)), Some(0));
+        assert_eq!(stockpiles.zone_at((1, 0)), Some(1));
+        assert!(stockpiles.zones[1].accepts(None));
+    }
+
+    #[test]
+    fn capacity_counts_items_on_the_way() {
+        let mut tiles = Tiles::from_rows(&[
+            ".....",
+            ".....",
+        ]);
+        let mut stockpiles = Stockpiles::new();
+        let mut reservations = Reservations::new();
+        stockpiles.designate(&tiles, &[(0, 0), (1, 0)], vec![::RESOURCE_STONE], 2);
+        drop_item(&mut tiles, (0, 0), ::SPRITE_ROCK, ::RESOURCE_STONE);
+        let food = drop_item(&mut tiles, (4, 0), ::SPRITE_FOOD, ::RESOURCE_FOOD);
+        let rock = drop_item(&mut tiles, (4, 1), ::SPRITE_ROCK, ::RESOURCE_STONE);
+        let search = pathfinding::search(&tiles, (4, 0), None, pathfinding::map_bounds(&tiles));
+        assert_eq!(stockpiles.find_free(&tiles, &reservations, 1, &search, food), None);
+        let mut found = Vec::new();
+        for miner_id in 1..5 {
+            match stockpiles.find_free(&tiles, &reservations, miner_id, &search, rock) {
+                Some((cell, slot)) => {
+                    assert!(reservations.reserve(slot, miner_id));
+                    found.push(cell);
+                },
+                None => break,
+            }
+        }
+        // two haulers fit on the closer cell, one more next to the rock lying there
+        assert_eq!(found, vec![(1, 0), (1, 0), (0, 0)]);
+        reservations.release_all(3);
+        assert_eq!(stockpiles.find_free(&tiles, &reservations, 4, &search, rock).map(|(cell, _)| cell), Some((0, 0)));
+    }
+
+    #[test]
+    fn totals() {
+        let mut tiles = Tiles::from_rows(&[
+            "....",
+        ]);
+        let mut stockpiles = Stockpiles::new();
+        stockpiles.designate(&tiles, &[(0, 0), (1, 0)], vec![::RESOURCE_STONE], 3);
+        let rock = drop_item(&mut tiles, (0, 0), ::SPRITE_ROCK, ::RESOURCE_STONE);
+        drop_item(&mut tiles, (1, 0), ::SPRITE_ROCK, ::RESOURCE_STONE);
+        tiles.tiles[rock].resource_count = 2;
+        // what lies elsewhere or isn't taken here doesn't count
+        drop_item(&mut tiles, (1, 0), ::SPRITE_FOOD, ::RESOURCE_FOOD);
+        drop_item(&mut tiles, (3, 0), ::SPRITE_ROCK, ::RESOURCE_STONE);
+        let totals = stockpiles.totals(&tiles);
+        assert_eq!(totals.get(&::RESOURCE_STONE), Some(&7));
+        assert_eq!(totals.get(&::RESOURCE_FOOD), None);
+        assert!(stockpiles.is_stored(&tiles, rock));
+    }
+}
@@ -0,0 +1,106 @@
+use tiles;
+use tiles::{Tiles, TileEvent};
+
+/// How far from a wall or a pillar a ceiling still holds, in cells
+const SUPPORT_RANGE: isize = 3;
+/// Seconds between a ceiling losing its support and coming down
+const COLLAPSE_DELAY: f32 = 5.0;
+
+/// Keeps track of ceilings that lost their support and brings them down.
+pub struct Structure {
+    /// Unsupported cells and the time left until they collapse
+    pub pending: Vec<((usize, usize), f32)>,
+    /// Last known support and roof of every cell, to skip changes that don't matter
+    supports: Vec<bool>,
+    roofed: Vec<bool>,
+}
+
+impl Structure {
+    pub fn new(tiles: &Tiles) -> Structure {
+        Structure {
+            pending: Vec::new(),
+            supports: tiles.ground.iter().map(|&id| tiles::is_support(tiles.tiles[id].tex_id)).collect(),
+            roofed: tiles.roofed.clone(),
+        }
+    }
+
+    fn is_supported(&self, cell: (usize, usize), tiles: &Tiles) -> bool {
+        for dx in -SUPPORT_RANGE..(SUPPORT_RANGE + 1) {
+            for dy in -SUPPORT_RANGE..(SUPPORT_RANGE + 1) {
+                let (x, y) = (cell.0 as isize + dx, cell.1 as isize + dy);
+                if x < 0 || y < 0 || x >= tiles.width as isize || y >= tiles.height as isize {
+                    continue;
+                }
+                if tiles::is_support(tiles.ground_at((x as usize, y as usize)).tex_id) {
+                    return true;
+                }
+            }
+        }
+        false
+    }
+
+    /// Rechecks the ceilings around the cells whose ground has changed.
+    pub fn handle_events(&mut self, tiles: &Tiles, events: &[TileEvent]) {
+        for event in events.iter() {
+            let id = match *event {
+                TileEvent::Changed(id) => id,
+                _ => continue,
+            };
+            let cell = match tiles.cell_at(tiles.tiles[id].position) {
+                Some(cell) => cell,
+                None => continue,
+            };
+            let cell_idx = tiles.cell_index(cell);
+            if tiles.ground[cell_idx] != id {
+                continue;
+            }
+            let supports = tiles::is_support(tiles.tiles[id].tex_id);
+            if self.supports[cell_idx] == supports && self.roofed[cell_idx] == tiles.roofed[cell_idx] {
+                continue;
+            }
+            self.supports[cell_idx] = supports;
+            self.roofed[cell_idx] = tiles.roofed[cell_idx];
+            self.check_around(cell, tiles);
+        }
+    }
+
+    fn check_around(&mut self, cell: (usize, usize), tiles: &Tiles) {
+        for dx in -SUPPORT_RANGE..(SUPPORT_RANGE + 1) {
+            for dy in -SUPPORT_RANGE..(SUPPORT_RANGE + 1) {
+                let (x, y) = (cell.0 as isize + dx, cell.1 as isize + dy);
+                if x < 0 || y < 0 || x >= tiles.width as isize || y >= tiles.height as isize {
+                    continue;
+                }
+                let around = (x as usize, y as usize);
+                let is_pending = self.pending.iter().any(|&(c, _)| c == around);
+                if tiles.roofed[tiles.cell_index(around)] && !self.is_supported(around, tiles) {
+                    if !is_pending {
+                        println!("Ceiling at {:?} lost its support", around);
+                        self.pending.push((around, COLLAPSE_DELAY));
+                    }
+                } else if is_pending {
+                    self.pending.retain(|&(c, _)| c != around);
+                }
+            }
+        }
+    }
+
+    /// Counts down the unsupported ceilings and collapses the ones whose time is up.
+    ///
+    /// Returns the cells that caved in.
+    pub fn update(&mut self, duration: f32, tiles: &mut Tiles) -> Vec<(usize, usize)> {
+        let mut collapsed = Vec::new();
+        for pending in self.pending.iter_mut() {
+            pending.1 -= duration;
+            if pending.1 <= 0.0 {
+                collapsed.push(pending.0);
+            }
+        }
+        self.pending.retain(|&(_, time_left)| time_left > 0.0);
+        for cell in collapsed.iter() {
+            println!("Cave-in at {:?}", cell);
+            tiles.collapse(*cell);
+        }
+        collapsed
+    }
+}
@@ -121,7 +121,9 @@ fn run<A, B, S>((width, height): (u32, u32),
         graphics_pool.reset();
         let frame = swap_chain.acquire_frame(FrameSync::Semaphore(&sync.acquisition));
         // render some text into an SDL surface
-        let text_surface = font.render(&format!("FPS {:.1}", fps))
+        let status = app.status();
+        let text = if status.is_empty() { format!("FPS {:.1}", fps) } else { format!("FPS {:.1} | {}", fps, status) };
+        let text_surface = font.render(&text)
             .blended(Color::RGBA(200, 200, 200, 255)).unwrap();
         app.render(&mut device, (frame, &sync), &mut graphics_pool, &mut queue, text_surface);
         swap_chain.present(&mut queue, &[]);
@@ -153,6 +155,11 @@ pub trait Application<B: Backend>: Sized {
     fn render(&mut self, device: &mut B::Device, frame: (gfx_core::Frame, &SyncPrimitives<B::Resources>),
                      pool: &mut GraphicsCommandPool<B>, queue: &mut GraphicsQueue<B>, text_surface: sdl2::surface::Surface);
 
+    /// Line of text shown after the frame rate.
+    fn status(&self) -> String {
+        String::new()
+    }
+
     fn on_resize(&mut self, WindowTargets<B::Resources>) {}
     fn on_resize_ext(&mut self, _device: &mut B::Device, targets: WindowTargets<B::Resources>) {
         self.on_resize(targets);
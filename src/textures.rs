@@ -2,7 +2,7 @@ use find_folder;
 use image;
 use gfx;
 use sdl2;
-const SPRITE_COUNT: usize = 7;
+const SPRITE_COUNT: usize = 20;
 
 use support::ColorFormat;
 
@@ -17,7 +17,11 @@ pub fn load_textures<R, D>(device: &mut D) -> gfx::handle::ShaderResourceView<R,
         "miner.png",
         "water.png",
         "grass.png", "clay.png", "stone.png",
-        "tree.png", "wood.png"];
+        "tree.png", "wood.png",
+        "floor.png", "smooth_floor.png", "hole.png", "rock.png",
+        "pillar.png", "shrub.png", "food.png", "corpse.png",
+        "deer.png", "bird.png", "wolf.png", "drink.png",
+        "bed.png"];
 
     let texture = {
         let images = tex_files.iter().map(|x| {
@@ -28,7 +32,11 @@ pub fn load_textures<R, D>(device: &mut D) -> gfx::handle::ShaderResourceView<R,
             &images[0],
             &images[1],
             &images[2], &images[3], &images[4],
-            &images[5], &images[6]];
+            &images[5], &images[6],
+            &images[7], &images[8], &images[9], &images[10],
+            &images[11], &images[12], &images[13], &images[14],
+            &images[15], &images[16], &images[17], &images[18],
+            &images[19]];
 
         device.create_texture_immutable_u8::<ColorFormat>(
             gfx::texture::Kind::D2Array(64, 64, SPRITE_COUNT as u16, gfx::texture::AaMode::Single),
@@ -9,6 +9,7 @@ use cgmath::{ InnerSpace, Vector2 };
 use selection;
 use std::iter::Iterator;
 use std::collections::HashSet;
+use std::mem;
 use quadtree::QuadTree;
 
 fn get_ground_tile_id() -> u32 {
@@ -22,7 +23,8 @@ fn get_ground_tile_id() -> u32 {
 
 fn get_resource_tile_id() -> Option<u32> {
     let mut items = vec!(Weighted { weight: 20, item: -1 }, // nothing
-                         Weighted { weight: 1, item: ::SPRITE_TREE as i32 });
+                         Weighted { weight: 1, item: ::SPRITE_TREE as i32 },
+                         Weighted { weight: 1, item: ::SPRITE_SHRUB as i32 });
     let wc = WeightedChoice::new(&mut items);
     let mut rng = rand::thread_rng();
     let maybe_id = wc.ind_sample(&mut rng);
@@ -32,6 +34,32 @@ fn get_resource_tile_id() -> Option<u32> {
     None
 }
 
+/// A change to `Tiles`, identified by tile index.
+///
+/// Indices stay valid after a removal: the slot is kept, marked removed, and never reused.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum TileEvent {
+    Changed(usize),
+    Spawned(usize),
+    Removed(usize),
+}
+
+/// Whether miners can stand on ground of this kind.
+pub fn is_walkable(tex_id: u32) -> bool {
+    tex_id != ::SPRITE_STONE && tex_id != ::SPRITE_WATER && tex_id != ::SPRITE_HOLE && tex_id != ::SPRITE_PILLAR
+}
+
+/// Whether ground of this kind holds up the ceiling around it.
+pub fn is_support(tex_id: u32) -> bool {
+    tex_id == ::SPRITE_STONE || tex_id == ::SPRITE_PILLAR
+}
+
+/// Whether a cell is within a sight radius of another.
+fn in_sight(center: (usize, usize), radius: f32, cell: (usize, usize)) -> bool {
+    let (dx, dy) = (cell.0 as f32 - center.0 as f32, cell.1 as f32 - center.1 as f32);
+    (dx * dx + dy * dy).sqrt() <= radius
+}
+
 #[derive(Debug)]
 pub struct Tile {
     pub position: Vector2<f32>,
@@ -40,6 +68,9 @@ pub struct Tile {
     pub resource_id: Option<u8>,
     pub resource_count: u8,
     pub can_be_carried: bool,
+    pub is_discovered: bool,
+    pub is_visible: bool,
+    pub is_removed: bool,
 }
 
 impl Tile {
@@ -55,6 +86,10 @@ impl Tile {
             resource_count: resource_id.map_or(0, |v| 5), // TODO roll the count,
             can_be_carried: false,
             is_selected: false,
+            // nothing is known until a miner has seen it
+            is_discovered: false,
+            is_visible: false,
+            is_removed: false,
         }
     }
 }
@@ -66,6 +101,19 @@ pub struct Tiles {
     pub width: usize,
     pub height: usize,
     pub tree: QuadTree,
+    /// Index of the ground tile for every cell, `x * height + y`
+    pub ground: Vec<usize>,
+    /// Ground of the level below every cell, `x * height + y`
+    pub below: Vec<u32>,
+    /// Whether a cell has rock above it, i.e. it was dug out of a wall
+    pub roofed: Vec<bool>,
+    pub step_x: f32,
+    pub step_y: f32,
+    pub x_start: f32,
+    pub y_start: f32,
+    pub events: Vec<TileEvent>,
+    /// Cells and sight radii of the viewers at the last visibility update
+    pub viewers: Vec<((usize, usize), f32)>,
 }
 
 impl Tiles {
@@ -88,9 +136,43 @@ impl Tiles {
         let (size_x, size_y) = heightmap.dimensions();
         println!("Map: {:?}", heightmap.dimensions());
 
+        let mut tiles = Tiles::build(size_x as usize, size_y as usize, |x, y| {
+            let pixel = heightmap.get_pixel(x as u32, y as u32).to_rgb().data;
+            if pixel[0] < (layer_idx * layer_step) {
+                ::SPRITE_WATER
+            } else if pixel[0] > (layer_idx * layer_step * 2) {
+                ::SPRITE_STONE
+            } else {
+                get_ground_tile_id() // grass or clay
+            }
+        }, get_resource_tile_id);
+
+        // the level below is solid rock where the map rises above it
+        let below_idx = layer_idx.saturating_sub(1);
+        for x in 0..size_x {
+            for y in 0..size_y {
+                let pixel = heightmap.get_pixel(x, y).to_rgb().data;
+                let index = tiles.cell_index((x as usize, y as usize));
+                tiles.below[index] = if layer_idx == 0 || pixel[0] > (below_idx * layer_step * 2) {
+                    ::SPRITE_STONE
+                } else if pixel[0] < (below_idx * layer_step) {
+                    ::SPRITE_WATER
+                } else {
+                    ::SPRITE_FLOOR
+                };
+            }
+        }
+        tiles
+    }
+
+    /// Lays out a map cell by cell, rolling a resource on every walkable one. The level below is solid rock.
+    fn build<G, R>(size_x: usize, size_y: usize, mut ground_at: G, mut roll_resource: R) -> Tiles
+        where G: FnMut(usize, usize) -> u32, R: FnMut() -> Option<u32>
+    {
         let mut tiles = Vec::new();
         let mut walkable = Vec::new();
         let mut walkable_set = HashSet::new();
+        let mut ground = Vec::new();
 
         let sprite_size = 64.0;
         let (step_x, step_y) = (sprite_size / 2.0, 17.0);
@@ -112,15 +194,10 @@ impl Tiles {
         tree.split();
         for x in 0..size_x {
             for y in 0..size_y {
-                let pixel = heightmap.get_pixel(x,  y).to_rgb().data;
-                let mut tex_id = get_ground_tile_id(); // grass or clay
-                if pixel[0] < (layer_idx * layer_step) {
-                    tex_id = ::SPRITE_WATER;
-                } else if pixel[0] > (layer_idx * layer_step * 2) {
-                    tex_id = ::SPRITE_STONE;
-                }
+                let tex_id = ground_at(x, y);
 
                 let last_id = tiles.len();
+                ground.push(last_id);
                 tiles.push(
                     Tile::new(Vector2::new(
                         x_start - step_x * x as f32 + step_x * y as f32,
@@ -129,18 +206,20 @@ impl Tiles {
                 );
                 tree.insert(&tiles[last_id].position, last_id);
 
-                if tex_id != ::SPRITE_STONE && tex_id != ::SPRITE_WATER {
+                if is_walkable(tex_id) {
                     // store walkable index
                     walkable.push(last_id);
                     walkable_set.insert(last_id);
 
                     // roll a resource tile
-                    let resource_tile_id = get_resource_tile_id();
+                    let resource_tile_id = roll_resource();
                     if resource_tile_id.is_some() {
                         let _resource_tile_id = resource_tile_id.unwrap();
                         let mut _resource_id: Option<u8> = None;
                         if _resource_tile_id == ::SPRITE_TREE {
                             _resource_id = Some(::RESOURCE_WOOD);
+                        } else if _resource_tile_id == ::SPRITE_SHRUB {
+                            _resource_id = Some(::RESOURCE_FOOD);
                         }
                         tiles.push(
                             Tile::new(Vector2::new(
@@ -158,9 +237,134 @@ impl Tiles {
             tiles: tiles,
             walkable: walkable,
             walkable_set: walkable_set,
-            width: size_x as usize,
-            height: size_y as usize,
+            width: size_x,
+            height: size_y,
             tree: tree,
+            roofed: vec![false; ground.len()],
+            ground: ground,
+            below: vec![::SPRITE_STONE; size_x * size_y],
+            step_x: step_x,
+            step_y: step_y,
+            x_start: x_start,
+            y_start: y_start,
+            events: Vec::new(),
+            viewers: Vec::new(),
+        }
+    }
+
+    /// A bare map for tests, one row of cells per string: `.` grass, `,` clay, `#` stone, `~` water.
+    ///
+    /// Row `y` holds the cells `(x, y)`.
+    #[cfg(test)]
+    pub fn from_rows(rows: &[&str]) -> Tiles {
+        let cells = rows.iter().map(|row| row.chars().collect::<Vec<_>>()).collect::<Vec<_>>();
+        Tiles::build(cells[0].len(), cells.len(), |x, y| match cells[y][x] {
+            ',' => ::SPRITE_CLAY,
+            '#' => ::SPRITE_STONE,
+            '~' => ::SPRITE_WATER,
+            _ => ::SPRITE_GRASS,
+        }, || None)
+    }
+
+    /// Hands over all changes since the last call.
+    pub fn take_events(&mut self) -> Vec<TileEvent> {
+        mem::replace(&mut self.events, Vec::new())
+    }
+
+    /// Returns the grid cell under a world position.
+    ///
+    /// Inverts the isometric projection used when the layer was built:
+    /// `pos.x - x_start` is `(y - x) * step_x` and `y_start - pos.y` is `(x + y) * step_y`.
+    pub fn cell_at(&self, position: Vector2<f32>) -> Option<(usize, usize)> {
+        let a = (position.x - self.x_start) / self.step_x;
+        let b = (self.y_start - position.y) / self.step_y;
+        let x = ((b - a) / 2.0).round();
+        let y = ((a + b) / 2.0).round();
+        if x < 0.0 || y < 0.0 || x >= self.width as f32 || y >= self.height as f32 {
+            return None;
+        }
+        Some((x as usize, y as usize))
+    }
+
+    pub fn cell_position(&self, cell: (usize, usize)) -> Vector2<f32> {
+        let (x, y) = (cell.0 as f32, cell.1 as f32);
+        Vector2::new(
+            self.x_start - self.step_x * x + self.step_x * y,
+            self.y_start - self.step_y * x - self.step_y * y,
+        )
+    }
+
+    pub fn cell_index(&self, cell: (usize, usize)) -> usize {
+        cell.0 * self.height + cell.1
+    }
+
+    pub fn is_discovered(&self, cell: (usize, usize)) -> bool {
+        self.tiles[self.ground[self.cell_index(cell)]].is_discovered
+    }
+
+    /// The tiles on a cell jobs and needs can use: its items and, last, its ground.
+    ///
+    /// With `discovered_only` only the ones the colony has seen are returned.
+    pub fn resources_at(&self, cell: (usize, usize), discovered_only: bool) -> Vec<usize> {
+        let mut ids = self.items_at(cell);
+        ids.push(self.ground[self.cell_index(cell)]);
+        ids.retain(|&id| self.tiles[id].is_discovered || !discovered_only);
+        ids
+    }
+
+    /// Updates what the colony can currently see around the viewers that moved and marks it as discovered.
+    ///
+    /// Every viewer is a world position and a sight radius in cells.
+    pub fn update_visibility(&mut self, viewers: &[(Vector2<f32>, f32)]) {
+        let viewers = viewers.iter()
+            .filter_map(|&(position, radius)| self.cell_at(position).map(|cell| (cell, radius)))
+            .collect::<Vec<_>>();
+        // only the cells around viewers that came, went or moved can change
+        let mut around = HashSet::new();
+        for &(center, radius) in viewers.iter().filter(|viewer| !self.viewers.contains(viewer))
+            .chain(self.viewers.iter().filter(|viewer| !viewers.contains(viewer))) {
+            around.extend(self.cells_in_sight(center, radius));
+        }
+        for cell in around {
+            let is_visible = viewers.iter().any(|&(center, radius)| in_sight(center, radius, cell));
+            for id in self.resources_at(cell, false) {
+                let tile = &mut self.tiles[id];
+                if tile.is_visible != is_visible || (is_visible && !tile.is_discovered) {
+                    self.events.push(TileEvent::Changed(id));
+                }
+                tile.is_visible = is_visible;
+                if is_visible {
+                    tile.is_discovered = true;
+                }
+            }
+        }
+        self.viewers = viewers;
+    }
+
+    /// The cells of the map within a sight radius of a cell.
+    fn cells_in_sight(&self, center: (usize, usize), radius: f32) -> Vec<(usize, usize)> {
+        let r = radius.ceil() as usize;
+        let mut cells = Vec::new();
+        for x in center.0.saturating_sub(r)..(center.0 + r + 1).min(self.width) {
+            for y in center.1.saturating_sub(r)..(center.1 + r + 1).min(self.height) {
+                if in_sight(center, radius, (x, y)) {
+                    cells.push((x, y));
+                }
+            }
+        }
+        cells
+    }
+
+    /// Gives an item the fog of war of the cell it is on.
+    fn share_fog(&mut self, id: usize) {
+        let cell = self.cell_at(self.tiles[id].position);
+        if cell.is_some() {
+            let (is_discovered, is_visible) = {
+                let ground = self.ground_at(cell.unwrap());
+                (ground.is_discovered, ground.is_visible)
+            };
+            self.tiles[id].is_discovered = is_discovered;
+            self.tiles[id].is_visible = is_visible;
         }
     }
 
@@ -186,6 +390,14 @@ impl Tiles {
         .map(|&i| &self.tiles[i]).collect::<Vec<_>>()
     }
 
+    /// Cells on the border of the map miners can stand on.
+    pub fn edge_walkable(&self) -> Vec<(usize, usize)> {
+        let (w, h) = (self.width, self.height);
+        let border = (0..w).flat_map(|x| vec![(x, 0), (x, h - 1)])
+            .chain((1..h - 1).flat_map(|y| vec![(0, y), (w - 1, y)]));
+        border.filter(|&cell| is_walkable(self.ground_at(cell).tex_id)).collect()
+    }
+
     pub fn get_closest_walkable(&self, pos: Vector2<f32>) -> Option<&Tile> {
         let tile_id = self.tree.find_around_in(&pos, &self.walkable_set);
         if tile_id.is_some() {
@@ -201,37 +413,35 @@ impl Tiles {
 
     pub fn update_selected(&mut self, selection: &selection::Selection) {
         // find selected tiles
-        for tile in self.tiles.iter_mut() {
-            if selection.pressed {
-                if selection.is_selected(tile.position) {
-                    tile.is_selected = true;
-                } else {
-                    tile.is_selected = false;
-                }
-            } else {
-                tile.is_selected = false;
-            }
+        for i in 0..self.tiles.len() {
+            let is_selected = selection.pressed && selection.is_selected(self.tiles[i].position);
+            self.set_selected(i, is_selected);
         }
     }
 
-    pub fn resource_at(&self, position: Vector2<f32>) -> Option<&Tile> {
-        let tile_ids = self.tree.find_all(&position);
-        if tile_ids.is_some() {
-            for tile_id in (*(tile_ids.unwrap())).iter() {
-                let tile = &self.tiles[*tile_id];
-                if tile.resource_count > 0 {
-                    return Some(tile);
-                }
-            }
+    /// Cells with a selected tile on them.
+    pub fn selected_cells(&self) -> Vec<(usize, usize)> {
+        let mut cells = self.tiles.iter()
+            .filter(|tile| tile.is_selected && !tile.is_removed)
+            .filter_map(|tile| self.cell_at(tile.position))
+            .collect::<Vec<_>>();
+        cells.sort();
+        cells.dedup();
+        cells
+    }
+
+    pub fn set_selected(&mut self, id: usize, is_selected: bool) {
+        if self.tiles[id].is_selected != is_selected {
+            self.tiles[id].is_selected = is_selected;
+            self.events.push(TileEvent::Changed(id));
         }
-        None
     }
 
     pub fn tile_at(&mut self, position: Vector2<f32>) -> Option<&Tile> {
         let tile_id = self.tree.find(&position);
         if tile_id.is_some() {
             let i = tile_id.unwrap();
-            self.tiles[i].is_selected = true;
+            self.set_selected(i, true);
             Some(&self.tiles[i])
         } else {
             None
@@ -242,12 +452,84 @@ impl Tiles {
         self.tiles.iter().position(|ref r| r.resource_count > 0 && r.position.x == tile.position.x && r.position.y == tile.position.y)
     }
 
+    /// Adds a tile to the world in a new slot, so that an index never names two tiles.
+    pub fn spawn(&mut self, tile: Tile) -> usize {
+        self.tiles.push(tile);
+        let id = self.tiles.len() - 1;
+        self.share_fog(id);
+        self.tree.insert(&self.tiles[id].position, id);
+        self.events.push(TileEvent::Spawned(id));
+        id
+    }
+
+    /// Picks a plant clean, leaving its food on the ground.
+    ///
+    /// Returns the index of the food.
+    pub fn gather(&mut self, id: usize) -> Option<usize> {
+        if self.tiles[id].is_removed || self.tiles[id].tex_id != ::SPRITE_SHRUB {
+            return None;
+        }
+        let mut food = Tile::new(self.tiles[id].position, ::SPRITE_FOOD, Some(::RESOURCE_FOOD));
+        food.resource_count = self.tiles[id].resource_count;
+        food.can_be_carried = true;
+        self.remove(Some(id));
+        Some(self.spawn(food))
+    }
+
+    /// Takes up to `count` portions out of a resource, removing it once it's used up.
+    ///
+    /// Returns the number of portions taken.
+    pub fn consume(&mut self, id: usize, count: u8) -> u8 {
+        let taken = count.min(self.tiles[id].resource_count);
+        self.tiles[id].resource_count -= taken;
+        if self.tiles[id].resource_count == 0 {
+            self.remove(Some(id));
+        } else if taken > 0 {
+            self.events.push(TileEvent::Changed(id));
+        }
+        taken
+    }
+
+    /// Lifts an item off the ground. It stays out of the spatial index until it's put down.
+    pub fn pick_up(&mut self, id: usize) {
+        let position = self.tiles[id].position;
+        self.tree.remove_at(&position, id);
+        self.events.push(TileEvent::Changed(id));
+    }
+
+    /// Moves an item that is being carried along.
+    ///
+    /// Nothing else changes on the way, so there is no event: only the drawing of the item has to follow.
+    pub fn carry(&mut self, id: usize, position: Vector2<f32>) {
+        self.tiles[id].position = position;
+        self.share_fog(id);
+    }
+
+    /// Puts a carried item down on a cell.
+    pub fn put_down(&mut self, id: usize, cell: (usize, usize)) {
+        let position = self.cell_position(cell);
+        self.tiles[id].position = position;
+        self.share_fog(id);
+        self.tree.insert(&position, id);
+        self.events.push(TileEvent::Changed(id));
+    }
+
+    /// Takes a tile out of the world, keeping the indices of all other tiles.
     pub fn remove(&mut self, tile_id: Option<usize>) -> Option<usize> {
         if tile_id.is_some() {
-            let old_len = self.tiles.len();
             let _tile_id = tile_id.unwrap();
-            self.tiles.remove(_tile_id);
+            if self.tiles[_tile_id].is_removed {
+                return None;
+            }
+            let tile = &mut self.tiles[_tile_id];
+            tile.is_removed = true;
+            tile.is_selected = false;
+            tile.resource_id = None;
+            tile.resource_count = 0;
+            tile.can_be_carried = false;
             self.tree.remove(_tile_id);
+            self.events.push(TileEvent::Removed(_tile_id));
+            return tile_id;
         }
         None
     }
@@ -256,6 +538,134 @@ impl Tiles {
         if id.is_some() {
             self.tiles[id.unwrap()].tex_id = tex_id;
             self.tiles[id.unwrap()].can_be_carried = can_be_carried;
+            self.events.push(TileEvent::Changed(id.unwrap()));
+        }
+    }
+}
+
+/// Terrain modification
+impl Tiles {
+    pub fn ground_at(&self, cell: (usize, usize)) -> &Tile {
+        &self.tiles[self.ground[self.cell_index(cell)]]
+    }
+
+    /// Returns all the tiles standing on a cell, apart from the ground itself.
+    pub fn items_at(&self, cell: (usize, usize)) -> Vec<usize> {
+        let position = self.cell_position(cell);
+        let ground_id = self.ground[self.cell_index(cell)];
+        match self.tree.find_all(&position) {
+            Some(ids) => ids.iter()
+                .filter(|&&id| id != ground_id && self.tiles[id].position == position)
+                .map(|&id| id)
+                .collect::<Vec<_>>(),
+            None => Vec::new(),
+        }
+    }
+
+    /// Changes the ground of a cell and keeps the walkable index in sync with it.
+    fn set_ground(&mut self, cell: (usize, usize), tex_id: u32) {
+        let ground_id = self.ground[self.cell_index(cell)];
+        let was_walkable = is_walkable(self.tiles[ground_id].tex_id);
+        self.replace(Some(ground_id), tex_id, false);
+        if was_walkable && !is_walkable(tex_id) {
+            self.walkable.retain(|&id| id != ground_id);
+            self.walkable_set.remove(&ground_id);
+        } else if !was_walkable && is_walkable(tex_id) {
+            self.walkable.push(ground_id);
+            self.walkable_set.insert(ground_id);
+        }
+    }
+
+    /// Digs a stone wall out into a rough floor, leaving a rock behind.
+    ///
+    /// Returns the index of the rock, or `None` if there was no wall to dig.
+    pub fn dig(&mut self, cell: (usize, usize)) -> Option<usize> {
+        if self.ground_at(cell).tex_id != ::SPRITE_STONE {
+            return None;
+        }
+        self.set_ground(cell, ::SPRITE_FLOOR);
+        let cell_idx = self.cell_index(cell);
+        self.roofed[cell_idx] = true;
+        let mut rock = Tile::new(self.cell_position(cell), ::SPRITE_ROCK, Some(::RESOURCE_STONE));
+        rock.resource_count = 1;
+        rock.can_be_carried = true;
+        Some(self.spawn(rock))
+    }
+
+    /// Opens a hole in the floor down to the level below.
+    ///
+    /// The cell has to be walkable and empty. Rock below is dug out into a floor, water below floods the hole.
+    pub fn channel(&mut self, cell: (usize, usize)) -> bool {
+        if !is_walkable(self.ground_at(cell).tex_id) || self.items_at(cell).len() > 0 {
+            return false;
+        }
+        let index = self.cell_index(cell);
+        if self.below[index] == ::SPRITE_WATER {
+            self.set_ground(cell, ::SPRITE_WATER);
+        } else {
+            self.below[index] = ::SPRITE_FLOOR;
+            self.set_ground(cell, ::SPRITE_HOLE);
+        }
+        true
+    }
+
+    /// Fills a cell with a material, burying whatever was standing on it if it becomes solid.
+    pub fn fill(&mut self, cell: (usize, usize), tex_id: u32) -> bool {
+        if self.ground_at(cell).tex_id == tex_id {
+            return false;
+        }
+        if !is_walkable(tex_id) {
+            for id in self.items_at(cell) {
+                self.remove(Some(id));
+            }
+            let cell_idx = self.cell_index(cell);
+            self.roofed[cell_idx] = false;
+        }
+        self.set_ground(cell, tex_id);
+        true
+    }
+
+    /// Builds a pillar holding up the ceiling on an empty floor.
+    pub fn build_pillar(&mut self, cell: (usize, usize)) -> bool {
+        if !is_walkable(self.ground_at(cell).tex_id) || self.items_at(cell).len() > 0 {
+            return false;
+        }
+        self.fill(cell, ::SPRITE_PILLAR)
+    }
+
+    /// Puts a bed up on an empty floor.
+    ///
+    /// Returns the index of the bed.
+    pub fn build_bed(&mut self, cell: (usize, usize)) -> Option<usize> {
+        if !is_walkable(self.ground_at(cell).tex_id) || self.items_at(cell).len() > 0 {
+            return None;
+        }
+        let position = self.cell_position(cell);
+        Some(self.spawn(Tile::new(position, ::SPRITE_BED, None)))
+    }
+
+    /// Brings the ceiling of a cell down, leaving rubble on the floor.
+    ///
+    /// Returns the index of the rubble.
+    pub fn collapse(&mut self, cell: (usize, usize)) -> Option<usize> {
+        let cell_idx = self.cell_index(cell);
+        if !self.roofed[cell_idx] {
+            return None;
+        }
+        self.roofed[cell_idx] = false;
+        self.events.push(TileEvent::Changed(self.ground[cell_idx]));
+        let mut rubble = Tile::new(self.cell_position(cell), ::SPRITE_ROCK, Some(::RESOURCE_STONE));
+        rubble.resource_count = 1;
+        rubble.can_be_carried = true;
+        Some(self.spawn(rubble))
+    }
+
+    /// Smooths a rough floor.
+    pub fn smooth(&mut self, cell: (usize, usize)) -> bool {
+        if self.ground_at(cell).tex_id != ::SPRITE_FLOOR {
+            return false;
         }
+        self.set_ground(cell, ::SPRITE_SMOOTH_FLOOR);
+        true
     }
 }
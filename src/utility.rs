@@ -0,0 +1,81 @@
+use std::fmt::Debug;
+
+/// One reason for or against an option, from 0 (rules it out) to 1 (nothing against it)
+#[derive(Debug)]
+pub struct Consideration {
+    pub name: &'static str,
+    pub score: f32,
+}
+
+/// Something that could be done next and why.
+pub struct Candidate<T> {
+    pub option: T,
+    pub considerations: Vec<Consideration>,
+}
+
+impl<T> Candidate<T> {
+    pub fn new(option: T) -> Candidate<T> {
+        Candidate {
+            option: option,
+            considerations: Vec::new(),
+        }
+    }
+
+    pub fn consider(mut self, name: &'static str, score: f32) -> Candidate<T> {
+        self.considerations.push(Consideration { name: name, score: score.max(0.0).min(1.0) });
+        self
+    }
+
+    /// Product of the considerations, so any one of them can veto the option.
+    pub fn score(&self) -> f32 {
+        self.considerations.iter().fold(1.0, |score, consideration| score * consideration.score)
+    }
+}
+
+/// Picks the candidate with the best score above zero, logging every score that went into it.
+pub fn choose<T: Copy + Debug>(who: &str, candidates: &[Candidate<T>]) -> Option<T> {
+    if candidates.len() == 0 {
+        return None;
+    }
+    let mut best: Option<(f32, T)> = None;
+    let mut log = Vec::new();
+    for candidate in candidates.iter() {
+        let score = candidate.score();
+        let reasons = candidate.considerations.iter()
+            .map(|c| format!("{} {:.2}", c.name, c.score))
+            .collect::<Vec<_>>();
+        log.push(format!("{:?} {:.3} [{}]", candidate.option, score, reasons.join(", ")));
+        if score > 0.0 && best.map_or(true, |(b, _)| score > b) {
+            best = Some((score, candidate.option));
+        }
+    }
+    let choice = best.map(|(_, option)| option);
+    println!("{} chose {:?}: {}", who, choice, log.join("; "));
+    choice
+}
+
+/// Goes from 1 when right there down to 1/2 at `half` and on towards 0.
+pub fn falloff(distance: f32, half: f32) -> f32 {
+    1.0 / (1.0 + distance / half)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn best_score_wins() {
+        let candidates = vec![
+            Candidate::new(1).consider("near", 0.5).consider("wanted", 0.5),
+            Candidate::new(2).consider("near", 0.9).consider("wanted", 0.4),
+            // ruled out, however good the rest looks
+            Candidate::new(3).consider("near", 1.0).consider("wanted", -0.5),
+        ];
+        assert_eq!(candidates[2].score(), 0.0);
+        assert_eq!(choose("test", &candidates), Some(2));
+        assert_eq!(choose("test", &candidates[2..]), None);
+        assert_eq!(choose::<u8>("test", &[]), None);
+        assert_eq!(falloff(0.0, 30.0), 1.0);
+        assert_eq!(falloff(30.0, 30.0), 0.5);
+    }
+}
@@ -0,0 +1,50 @@
+use rand;
+use rand::Rng;
+
+/// Seconds a shower lasts, at least and at most
+const SHOWER: (f32, f32) = (60.0, 180.0);
+/// Seconds between showers, at least and at most
+const DRY_SPELL: (f32, f32) = (300.0, 900.0);
+
+/// Showers coming and going over the map. Only roofed cells keep the rain off.
+pub struct Weather {
+    pub raining: bool,
+    /// Seconds until the weather turns
+    pub time_left: f32,
+}
+
+impl Weather {
+    pub fn new() -> Weather {
+        Weather {
+            raining: false,
+            time_left: DRY_SPELL.0,
+        }
+    }
+
+    /// Passes time and returns whether it is raining.
+    pub fn update(&mut self, duration: f32) -> bool {
+        self.time_left -= duration;
+        if self.time_left <= 0.0 {
+            self.raining = !self.raining;
+            let (min, max) = if self.raining { SHOWER } else { DRY_SPELL };
+            self.time_left = rand::thread_rng().gen_range(min, max);
+            println!("{}", if self.raining { "It starts to rain" } else { "The rain stops" });
+        }
+        self.raining
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn turns() {
+        let mut weather = Weather::new();
+        assert!(!weather.update(DRY_SPELL.0 - 1.0));
+        assert!(weather.update(1.0));
+        assert!(weather.time_left >= SHOWER.0 && weather.time_left < SHOWER.1);
+        assert!(!weather.update(SHOWER.1));
+        assert!(weather.time_left >= DRY_SPELL.0 && weather.time_left < DRY_SPELL.1);
+    }
+}
@@ -0,0 +1,398 @@
+use std::fs::File;
+use std::io::Read;
+use std::str::FromStr;
+use find_folder;
+use rand;
+use rand::Rng;
+use cgmath::Vector2;
+use cgmath::prelude::*;
+use hpa;
+use movement::{MovementState, Walker};
+use pathfinding;
+use tiles;
+
+/// How far in cells an animal wanders off at a time
+const WANDER_RANGE: isize = 8;
+/// Chance of an idle animal setting off every second
+const WANDER_RATE: f32 = 0.2;
+/// Seconds a grazing animal keeps its head down, at most
+const GRAZE_TIME: f32 = 15.0;
+/// How close in cells a miner gets before a hungry predator goes for it
+const HUNT_RANGE: f32 = 6.0;
+/// How close in cells a predator has to be to bite
+const BITE_RANGE: f32 = 1.0;
+pub const BITE_DAMAGE: f32 = 10.0;
+/// Seconds a predator leaves the miners alone after a bite
+const FED_TIME: f32 = 120.0;
+
+/// Where a species turns up
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Biome {
+    Grass,
+    Clay,
+    /// among the trees
+    Woods,
+}
+
+impl FromStr for Biome {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Biome, String> {
+        match name {
+            "grass" => Ok(Biome::Grass),
+            "clay" => Ok(Biome::Clay),
+            "woods" => Ok(Biome::Woods),
+            _ => Err(format!("Unknown biome '{}'", name)),
+        }
+    }
+}
+
+impl Biome {
+    fn contains(&self, tiles: &tiles::Tiles, cell: (usize, usize)) -> bool {
+        let ground = tiles.ground_at(cell).tex_id;
+        match *self {
+            Biome::Grass => ground == ::SPRITE_GRASS,
+            Biome::Clay => ground == ::SPRITE_CLAY,
+            Biome::Woods => tiles::is_walkable(ground)
+                && tiles.items_at(cell).iter().any(|&id| tiles.tiles[id].tex_id == ::SPRITE_TREE),
+        }
+    }
+}
+
+/// A kind of animal, as read from `assets/species.txt`.
+#[derive(Clone, Debug)]
+pub struct Species {
+    pub name: String,
+    pub tex_id: u32,
+    pub biome: Biome,
+    /// How many turn up together
+    pub herd: usize,
+    /// Distance walked per second
+    pub speed: f32,
+    /// How close in cells a miner or a predator gets before it runs, 0 for fearless
+    pub flees: f32,
+    pub grazes: bool,
+    pub predator: bool,
+}
+
+fn sprite(name: &str) -> Result<u32, String> {
+    match name {
+        "deer" => Ok(::SPRITE_DEER),
+        "bird" => Ok(::SPRITE_BIRD),
+        "wolf" => Ok(::SPRITE_WOLF),
+        _ => Err(format!("Unknown sprite '{}'", name)),
+    }
+}
+
+fn yes_no(word: &str) -> Result<bool, String> {
+    match word {
+        "yes" => Ok(true),
+        "no" => Ok(false),
+        _ => Err(format!("Expected yes or no, found '{}'", word)),
+    }
+}
+
+/// Reads a table of species, one per line with the columns
+/// `name sprite biome herd speed flees grazes predator`. `;` starts a comment.
+pub fn parse(source: &str) -> Result<Vec<Species>, String> {
+    let mut species = Vec::new();
+    for (number, line) in source.lines().enumerate() {
+        let fields = line.split(';').next().unwrap().split_whitespace().collect::<Vec<_>>();
+        if fields.len() == 0 {
+            continue;
+        }
+        let error = |message: String| format!("Line {}: {}", number + 1, message);
+        if fields.len() != 8 {
+            return Err(error(format!("expected 8 columns, found {}", fields.len())));
+        }
+        species.push(Species {
+            name: fields[0].to_string(),
+            tex_id: sprite(fields[1]).map_err(&error)?,
+            biome: fields[2].parse().map_err(&error)?,
+            herd: fields[3].parse().map_err(|_| error(format!("bad herd '{}'", fields[3])))?,
+            speed: fields[4].parse().map_err(|_| error(format!("bad speed '{}'", fields[4])))?,
+            flees: fields[5].parse().map_err(|_| error(format!("bad distance '{}'", fields[5])))?,
+            grazes: yes_no(fields[6]).map_err(&error)?,
+            predator: yes_no(fields[7]).map_err(&error)?,
+        });
+    }
+    Ok(species)
+}
+
+/// Reads the species from a file in the assets folder.
+pub fn load(filename: &str) -> Result<Vec<Species>, String> {
+    let assets = find_folder::Search::ParentsThenKids(3, 3)
+        .for_folder("assets").map_err(|e| format!("{:?}", e))?;
+    let mut source = String::new();
+    File::open(assets.join(filename))
+        .and_then(|mut file| file.read_to_string(&mut source))
+        .map_err(|e| format!("Can't read {}: {}", filename, e))?;
+    parse(&source)
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum AnimalState {
+    /// standing around or strolling
+    Wandering,
+    /// seconds left with its head down
+    Grazing(f32),
+    /// running away from what scared it
+    Fleeing,
+    /// going after a miner
+    Hunting,
+}
+
+pub struct Animal {
+    /// Index in `Wildlife::species`
+    pub species: usize,
+    pub tile: tiles::Tile,
+    pub movement_state: MovementState,
+    pub state: AnimalState,
+    pub waypoints: Vec<Vector2<f32>>,
+    /// Seconds until a predator is hungry again
+    pub fed: f32,
+}
+
+impl Walker for Animal {
+    fn position(&self) -> Vector2<f32> {
+        self.tile.position
+    }
+
+    fn set_position(&mut self, position: Vector2<f32>) {
+        self.tile.position = position;
+    }
+
+    fn waypoints(&mut self) -> &mut Vec<Vector2<f32>> {
+        &mut self.waypoints
+    }
+}
+
+/// The animals on the map.
+pub struct Wildlife {
+    /// Loaded from `assets/species.txt`
+    pub species: Vec<Species>,
+    pub animals: Vec<Animal>,
+}
+
+impl Wildlife {
+    /// Loads the species and lets a herd of each turn up in its biome.
+    pub fn new(tiles: &tiles::Tiles) -> Wildlife {
+        let species = match load("species.txt") {
+            Ok(species) => species,
+            Err(error) => panic!("Can't load the species: {}", error),
+        };
+        let mut wildlife = Wildlife {
+            species: species,
+            animals: Vec::new(),
+        };
+        for i in 0..wildlife.species.len() {
+            wildlife.spawn(i, tiles);
+        }
+        wildlife
+    }
+
+    /// A herd of a species turns up around some cell of its biome. Returns how many did.
+    pub fn spawn(&mut self, species: usize, tiles: &tiles::Tiles) -> usize {
+        let (biome, herd, tex_id) = {
+            let species = &self.species[species];
+            (species.biome, species.herd, species.tex_id)
+        };
+        let cells = (0..tiles.width)
+            .flat_map(|x| (0..tiles.height).map(move |y| (x, y)))
+            .filter(|&cell| biome.contains(tiles, cell))
+            .collect::<Vec<_>>();
+        let mut rng = rand::thread_rng();
+        let center = match rng.choose(&cells) {
+            Some(&cell) => cell,
+            None => return 0,
+        };
+        // the herd keeps together
+        let mut around = cells.iter()
+            .filter(|&&cell| pathfinding::heuristic(cell, center) <= WANDER_RANGE as f32)
+            .collect::<Vec<_>>();
+        rng.shuffle(&mut around);
+        for &&cell in around.iter().take(herd) {
+            self.animals.push(Animal {
+                species: species,
+                tile: tiles::Tile::new(tiles.cell_position(cell), tex_id, None),
+                movement_state: MovementState::Idle,
+                state: AnimalState::Wandering,
+                waypoints: Vec::new(),
+                fed: 0.0,
+            });
+        }
+        println!("{} {} turned up around {:?}", around.len().min(herd), self.species[species].name, center);
+        around.len().min(herd)
+    }
+
+    pub fn get_tiles(&self) -> Vec<&tiles::Tile> {
+        self.animals.iter().map(|animal| &animal.tile).collect()
+    }
+
+    /// Animals graze and wander about, and run from miners and predators coming too close.
+    /// Hungry predators go for the miners instead.
+    ///
+    /// Returns the indices in `miners` of the ones bitten.
+    pub fn update(&mut self, duration: f32, tiles: &tiles::Tiles, hpa: &hpa::Hpa, miners: &[Vector2<f32>])
+        -> Vec<usize>
+    {
+        let predators = self.animals.iter()
+            .filter(|animal| self.species[animal.species].predator)
+            .map(|animal| animal.tile.position)
+            .collect::<Vec<_>>();
+        let mut rng = rand::thread_rng();
+        let mut bitten = Vec::new();
+        for animal in self.animals.iter_mut() {
+            let species = &self.species[animal.species];
+            let cell = match tiles.cell_at(animal.tile.position) {
+                Some(cell) => cell,
+                None => continue,
+            };
+            // only seen where the miners can see
+            let seen = tiles.ground_at(cell).is_visible;
+            animal.tile.is_visible = seen;
+            animal.tile.is_discovered = seen;
+
+            let others = if species.predator { &predators[..0] } else { &predators[..] };
+            let threat = miners.iter().chain(others.iter())
+                .filter(|&&position| position != animal.tile.position
+                        && (position - animal.tile.position).magnitude() <= species.flees * tiles.step_x)
+                .min_by(|&&a, &&b| (a - animal.tile.position).magnitude()
+                        .partial_cmp(&(b - animal.tile.position).magnitude()).unwrap());
+            if threat.is_some() && (animal.state != AnimalState::Fleeing || animal.waypoints.len() == 0) {
+                // run the other way, as far again as it lets things come, but not off the map
+                let away = (animal.tile.position - *threat.unwrap()).normalize() * species.flees * 2.0 * tiles.step_x;
+                let mut refuge = None;
+                for &share in [1.0, 0.5, 0.25].iter() {
+                    if let Some(target) = tiles.cell_at(animal.tile.position + away * share) {
+                        refuge = tiles.get_closest_walkable(tiles.cell_position(target)).map(|tile| tile.position);
+                        break;
+                    }
+                }
+                if refuge.is_some() && animal.route_to(refuge.unwrap(), tiles, hpa) {
+                    animal.state = AnimalState::Fleeing;
+                }
+            }
+            if species.predator {
+                animal.fed = (animal.fed - duration).max(0.0);
+                let prey = miners.iter().enumerate()
+                    .map(|(i, &position)| (i, position, (position - animal.tile.position).magnitude()))
+                    .filter(|&(_, _, distance)| animal.fed <= 0.0 && distance <= HUNT_RANGE * tiles.step_x)
+                    .min_by(|a, b| a.2.partial_cmp(&b.2).unwrap());
+                match prey {
+                    Some((i, _, distance)) if distance <= BITE_RANGE * tiles.step_x => {
+                        bitten.push(i);
+                        animal.fed = FED_TIME;
+                        animal.waypoints.clear();
+                        animal.state = AnimalState::Wandering;
+                    },
+                    Some((_, position, _)) => {
+                        // keep after it as it moves
+                        if (animal.state != AnimalState::Hunting || animal.waypoints.len() == 0)
+                            && animal.route_to(position, tiles, hpa) {
+                            animal.state = AnimalState::Hunting;
+                        }
+                    },
+                    None if animal.state == AnimalState::Hunting => animal.state = AnimalState::Wandering,
+                    None => (),
+                }
+            }
+            match animal.state {
+                AnimalState::Fleeing if animal.waypoints.len() == 0 => animal.state = AnimalState::Wandering,
+                AnimalState::Grazing(left) => animal.state = if left > duration {
+                    AnimalState::Grazing(left - duration)
+                } else {
+                    AnimalState::Wandering
+                },
+                AnimalState::Wandering if animal.waypoints.len() == 0 && rng.gen::<f32>() < WANDER_RATE * duration => {
+                    if species.grazes && tiles.ground_at(cell).tex_id == ::SPRITE_GRASS && rng.gen::<bool>() {
+                        animal.state = AnimalState::Grazing(rng.gen::<f32>() * GRAZE_TIME);
+                    } else {
+                        let (dx, dy) = (rng.gen_range(-WANDER_RANGE, WANDER_RANGE + 1),
+                                        rng.gen_range(-WANDER_RANGE, WANDER_RANGE + 1));
+                        let (x, y) = (cell.0 as isize + dx, cell.1 as isize + dy);
+                        if pathfinding::cell_cost(tiles, x, y).is_some() {
+                            let target = tiles.cell_position((x as usize, y as usize));
+                            animal.route_to(target, tiles, hpa);
+                        }
+                    }
+                },
+                _ => (),
+            }
+            let speed = match animal.state {
+                AnimalState::Fleeing | AnimalState::Hunting => species.speed * 1.5,
+                _ => species.speed,
+            };
+            animal.movement_state = animal.walk(speed * duration);
+        }
+        bitten
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_species() {
+        let source = "; name sprite biome herd speed flees grazes predator\n\
+                      \n\
+                      deer deer grass 4 9 6 yes no ; shy\n\
+                      wolf  wolf  clay  3  11.5  0  no  yes\n";
+        let species = parse(source).unwrap();
+        assert_eq!(species.len(), 2);
+        assert_eq!(species[0].name, "deer");
+        assert_eq!(species[0].tex_id, ::SPRITE_DEER);
+        assert_eq!(species[0].biome, Biome::Grass);
+        assert_eq!((species[0].herd, species[0].flees, species[0].grazes), (4, 6.0, true));
+        assert_eq!((species[1].biome, species[1].speed, species[1].predator), (Biome::Clay, 11.5, true));
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert_eq!(parse("deer deer grass 4 9 6 yes").unwrap_err(), "Line 1: expected 8 columns, found 7");
+        assert_eq!(parse("\ndeer cow grass 4 9 6 yes no").unwrap_err(), "Line 2: Unknown sprite 'cow'");
+        assert_eq!(parse("deer deer swamp 4 9 6 yes no").unwrap_err(), "Line 1: Unknown biome 'swamp'");
+        assert_eq!(parse("deer deer grass -4 9 6 yes no").unwrap_err(), "Line 1: bad herd '-4'");
+        assert_eq!(parse("deer deer grass 4 fast 6 yes no").unwrap_err(), "Line 1: bad speed 'fast'");
+        assert_eq!(parse("deer deer grass 4 9 6 maybe no").unwrap_err(), "Line 1: Expected yes or no, found 'maybe'");
+    }
+
+    #[test]
+    fn wolves_bite_once_fed() {
+        let tiles = tiles::Tiles::from_rows(&[
+            "..........",
+            "..........",
+        ]);
+        let hpa = hpa::Hpa::new(&tiles);
+        let mut wildlife = Wildlife {
+            species: parse("wolf wolf clay 1 10 0 no yes").unwrap(),
+            animals: Vec::new(),
+        };
+        wildlife.animals.push(Animal {
+            species: 0,
+            tile: tiles::Tile::new(tiles.cell_position((0, 0)), ::SPRITE_WOLF, None),
+            movement_state: MovementState::Idle,
+            state: AnimalState::Wandering,
+            waypoints: Vec::new(),
+            fed: 0.0,
+        });
+        let miners = [tiles.cell_position((9, 1)), tiles.cell_position((4, 1))];
+        assert!(wildlife.update(0.1, &tiles, &hpa, &miners).is_empty());
+        assert_eq!(wildlife.animals[0].state, AnimalState::Hunting);
+        let mut bitten = Vec::new();
+        for _ in 0..100 {
+            bitten.extend(wildlife.update(0.1, &tiles, &hpa, &miners));
+        }
+        // the closest one, and only once
+        assert_eq!(bitten, vec![1]);
+        assert!(wildlife.animals[0].fed > 0.0);
+    }
+
+    #[test]
+    fn load_assets() {
+        let species = load("species.txt").unwrap();
+        assert!(species.iter().any(|species| species.predator));
+        assert!(species.iter().any(|species| species.flees > 0.0));
+    }
+}